@@ -0,0 +1,63 @@
+//! Benchmarks for assigning rows to centroids with `KMeans::predict`.
+//!
+//! `Distance::Euclidean` is specialized to `sq_l2_dist` in `KMeans`, so `predict_euclidean` should
+//! run as fast as `nearest_sq_l2_dist`, which computes the same assignment directly.
+
+#![feature(test)]
+
+extern crate test;
+
+use fact_graph::clustering::{distance::Distance, kmeans::KMeans};
+use ndarray::{parallel::prelude::*, prelude::*};
+use ndarray_stats::DeviationExt;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use test::Bencher;
+
+fn data() -> (Array2<f32>, Vec<Array1<f32>>) {
+    let rng = &mut Pcg64Mcg::seed_from_u64(0);
+    let data = Array2::from_shape_fn((2000, 64), |_| rng.gen_range(0.0, 1.0));
+    let centroids = (0..16)
+        .map(|_| Array1::from_shape_fn(64, |_| rng.gen_range(0.0, 1.0)))
+        .collect();
+    (data, centroids)
+}
+
+#[bench]
+fn predict_euclidean(b: &mut Bencher) {
+    let (data, centroids) = data();
+    b.iter(|| KMeans::predict(&data, &centroids, Distance::Euclidean));
+}
+
+#[bench]
+fn predict_manhattan(b: &mut Bencher) {
+    let (data, centroids) = data();
+    b.iter(|| KMeans::predict(&data, &centroids, Distance::Manhattan));
+}
+
+#[bench]
+fn nearest_sq_l2_dist(b: &mut Bencher) {
+    let (data, centroids) = data();
+    b.iter(|| {
+        data.axis_iter(Axis(0))
+            .into_par_iter()
+            .map(|row| {
+                centroids
+                    .iter()
+                    .map(|c| row.sq_l2_dist(c).unwrap())
+                    .enumerate()
+                    .fold(
+                        (0, f32::INFINITY),
+                        |best, (i, d)| {
+                            if d < best.1 {
+                                (i, d)
+                            } else {
+                                best
+                            }
+                        },
+                    )
+                    .0
+            })
+            .collect::<Vec<usize>>()
+    });
+}
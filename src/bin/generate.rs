@@ -1,14 +1,15 @@
 use fact_graph::{
-    config::construct_method,
-    input::{InputFormat, NddFile},
+    config::{construct_method, BINARY_GRAPHS, CLASS_KEY},
+    graph,
+    input::{parse_dir, Corpus, NddFile},
 };
 use rayon::prelude::*;
 use std::{
     env,
     error::Error,
-    fs::{self, File},
-    io::BufReader,
-    path::PathBuf,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
     process,
 };
 
@@ -20,30 +21,53 @@ fn main() {
         Err(e) => error("Unable to enter workdir", e),
     }
 
-    let files: Vec<_> = match fs::read_dir("input") {
-        Err(e) => error("Unable to read input directory", e),
-        Ok(dir_iter) => match dir_iter.collect() {
-            Ok(files) => files,
-            Err(e) => error("Error while reading input directory", e),
-        },
+    let mut corpus: Corpus = match parse_dir::<Format>(Path::new("input")) {
+        Ok(documents) => documents.into_iter().collect(),
+        Err(e) => error("Error reading input files", e),
     };
 
-    files.par_iter().for_each(|file| {
-        let reader = BufReader::new(match File::open(file.path()) {
-            Ok(f) => f,
-            Err(e) => error("Error opening file", e),
-        });
-        let document = match Format::parse(reader) {
-            Ok(d) => d,
-            Err(e) => error("Error parsing file", e),
+    if Path::new("labels").exists() {
+        let file = match File::open("labels") {
+            Ok(f) => BufReader::new(f),
+            Err(e) => error("Unable to open labels file", e),
         };
-        let graph = construct_method(&document);
-        let outpath: PathBuf = ["graphs".into(), file.file_name()].iter().collect();
+        match corpus.read_metadata(CLASS_KEY, file) {
+            Ok(()) => (),
+            Err(e) => error("Error reading labels file", e),
+        }
+    }
+    for i in 0..corpus.len() {
+        if !corpus.metadata(i).contains_key(CLASS_KEY) {
+            let class = corpus.names()[i].split('-').next().unwrap().to_string();
+            corpus.metadata_mut(i).insert(CLASS_KEY.to_string(), class);
+        }
+    }
+    let outfile = match File::create("classes") {
+        Ok(f) => f,
+        Err(e) => error("Unable to create output file", e),
+    };
+    match corpus.write_metadata(CLASS_KEY, io::BufWriter::new(outfile)) {
+        Ok(()) => (),
+        Err(e) => error("Failed to write classes", e),
+    }
+
+    for name in corpus.degenerate_documents() {
+        eprintln!("Warning: {} has fewer than two distinct terms", name);
+    }
+
+    corpus.par_iter().for_each(|(name, document)| {
+        let graph = construct_method(document);
+        let outpath: PathBuf = ["graphs", name].iter().collect();
         let outfile = match File::create(outpath) {
             Ok(f) => f,
             Err(e) => error("Unable to create output file", e),
         };
-        match serde_json::to_writer(outfile, &graph) {
+        let result = if BINARY_GRAPHS {
+            graph::io::write_binary(&graph, io::BufWriter::new(outfile))
+        } else {
+            serde_json::to_writer(outfile, &graph).map_err(io::Error::from)
+        };
+        match result {
             Ok(()) => (),
             Err(e) => error("Failed to serialize data.", e),
         }
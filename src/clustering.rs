@@ -1,13 +1,17 @@
 //! Module containing functions needed for the clustering process and implementations of
 //! clustering algorithms.
+pub mod distance;
 pub mod kmeans;
 pub mod kmeans_lib;
+pub mod metrics;
+pub mod sparse;
 
 use crate::{
-    config::{CV_INV_THRESHOLD, SIGMA_THRESHOLD},
+    config::{Config, CV_INV_THRESHOLD, SIGMA_THRESHOLD},
     graph::{Graph, IndexMap},
 };
 use ndarray::prelude::*;
+use ndarray_stats::DeviationExt;
 use rand::Rng;
 use rayon::prelude::*;
 use std::collections::HashMap;
@@ -44,24 +48,84 @@ pub trait Clustering {
     fn cluster<R: Rng>(data: &Array2<f32>, clusters: usize, rng: &mut R) -> Vec<usize>;
 }
 
-/// Converts graphs into a feature matrix.
-pub fn vectorize<T: Value>(graphs: &[Graph<T>]) -> Array2<f32> {
-    let language: IndexMap = graphs
-        .iter()
-        .map(|g| g.vertices())
-        .flatten()
-        .fold(HashMap::new(), |mut acc, w| {
-            *acc.entry(w).or_insert(0) += 1;
+/// Returns the vocabulary used by `vectorize`, which contains the terms found in more than three
+/// of the graphs.
+pub fn corpus_language<T: Value>(graphs: &[Graph<T>]) -> IndexMap {
+    build_corpus_language(graphs, 4)
+}
+
+/// Returns the terms found in at least `min_doc_freq` of the graphs.
+///
+/// The document frequencies are counted in parallel.
+pub fn build_corpus_language<T: Value>(graphs: &[Graph<T>], min_doc_freq: usize) -> IndexMap {
+    let mut terms: Vec<String> = graphs
+        .par_iter()
+        .fold(HashMap::new, |mut acc, g| {
+            for w in g.vertices() {
+                *acc.entry(w).or_insert(0) += 1;
+            }
             acc
         })
+        .reduce(HashMap::new, |mut a, b| {
+            for (w, count) in b {
+                *a.entry(w).or_insert(0) += count;
+            }
+            a
+        })
         .into_iter()
-        .filter(|&(_, v)| v > 3)
-        .map(|(k, _): (String, _)| k)
+        .filter(|&(_, count)| count >= min_doc_freq)
+        .map(|(w, _)| w)
         .collect();
+    terms.par_sort_unstable();
+    IndexMap::from_sorted(&terms)
+}
+
+/// Options for converting graphs into a feature matrix with `vectorize_with`.
+#[derive(Clone, Copy, Debug)]
+pub struct VectorizeOptions {
+    /// Whether self-loops are used as features. If `false`, the columns for self-loops are left
+    /// as zero, which keeps the column layout unchanged.
+    pub include_self_loops: bool,
+    /// Whether the weighted degree of each term, as given by `weighted_degree`, is appended as a
+    /// feature after the edge columns. Terms not in a graph have a weighted degree of zero.
+    pub append_weighted_degree: bool,
+}
+
+impl Default for VectorizeOptions {
+    fn default() -> Self {
+        VectorizeOptions {
+            include_self_loops: true,
+            append_weighted_degree: false,
+        }
+    }
+}
+
+/// Converts graphs into a feature matrix.
+///
+/// Each column corresponds to a pair of terms from `corpus_language`; see
+/// `edge_index_to_term_indices` for the mapping. A term paired with itself corresponds to the
+/// self-loop of that term.
+pub fn vectorize<T: Value>(graphs: &[Graph<T>]) -> Array2<f32> {
+    vectorize_with(graphs, &VectorizeOptions::default())
+}
+
+/// Converts graphs into a feature matrix with the given options.
+///
+/// See `vectorize` for the layout of the edge columns. If `append_weighted_degree` is set, they
+/// are followed by one column for each term of `corpus_language`, in order, so the weighted degree
+/// of the term with index `i` is in column `dim * (dim + 1) / 2 + i`, where `dim` is the number of
+/// terms.
+pub fn vectorize_with<T: Value>(graphs: &[Graph<T>], options: &VectorizeOptions) -> Array2<f32> {
+    let language = corpus_language(graphs);
     let dim = language.len();
     let len = (dim * (dim + 1)) / 2;
     let n = graphs.len();
-    let mut res = Array2::zeros((n, len));
+    let degree_cols = if options.append_weighted_degree {
+        dim
+    } else {
+        0
+    };
+    let mut res = Array2::zeros((n, len + degree_cols));
     res.axis_iter_mut(Axis(0))
         .into_par_iter()
         .zip(graphs)
@@ -70,39 +134,740 @@ pub fn vectorize<T: Value>(graphs: &[Graph<T>]) -> Array2<f32> {
                 let v1 = language.get(&*v1);
                 let v2 = language.get(&*v2);
                 if let (Some(v1), Some(v2)) = (v1, v2) {
-                    row[term_indices_to_edge_index(v1, v2)] = e.value();
+                    if v1 != v2 || options.include_self_loops {
+                        row[term_indices_to_edge_index(v1, v2)] = e.value();
+                    }
+                    if options.append_weighted_degree {
+                        row[len + v1] += e.value();
+                        row[len + v2] += e.value();
+                    }
                 }
             });
         });
     res
 }
 
+/// Converts graphs into a sparse feature matrix.
+///
+/// The columns are the same as those of `vectorize`, but only the edges present in each graph are
+/// stored, so the matrix can be built for corpora whose dense feature matrix would not fit in
+/// memory. Reduce it with `sparse::reduce_dims_sparse`.
+pub fn vectorize_sparse<T: Value>(graphs: &[Graph<T>]) -> sparse::CsrMatrix {
+    let language = corpus_language(graphs);
+    let dim = language.len();
+    let rows = graphs
+        .par_iter()
+        .map(|g| {
+            let terms: Vec<Option<usize>> = g.vertices().map(|v| language.get(&*v)).collect();
+            g.fold_edges(Vec::new(), |mut acc, (row, col, e)| {
+                if let (Some(v1), Some(v2)) = (terms[row], terms[col]) {
+                    if e.value() != 0.0 {
+                        acc.push((term_indices_to_edge_index(v1, v2), e.value()));
+                    }
+                }
+                acc
+            })
+        })
+        .collect();
+    sparse::CsrMatrix::from_rows((dim * (dim + 1)) / 2, rows)
+}
+
 fn term_indices_to_edge_index(i1: usize, i2: usize) -> usize {
     let row = std::cmp::max(i1, i2);
     let col = std::cmp::min(i1, i2);
     (row * (row + 1)) / 2 + col
 }
 
+/// Converts a column index of a matrix produced by `vectorize` into the indices of the
+/// corresponding pair of terms in the language.
+///
+/// The return value is of the format (row, column), where row >= column.
+pub fn edge_index_to_term_indices(index: usize) -> (usize, usize) {
+    let mut row = ((((8 * index + 1) as f64).sqrt() - 1.0) / 2.0) as usize;
+    // Correct for floating point error in the estimate.
+    while (row * (row + 1)) / 2 > index {
+        row -= 1;
+    }
+    while ((row + 1) * (row + 2)) / 2 <= index {
+        row += 1;
+    }
+    (row, index - (row * (row + 1)) / 2)
+}
+
+/// Finds the term pairs that best distinguish each cluster from the data as a whole.
+///
+/// `data` must be a matrix produced by `vectorize` (before trimming), and `language` the
+/// vocabulary it was built with, as returned by `corpus_language`. For each cluster, features are
+/// ranked by how much their mean within the cluster exceeds their mean over all rows, and the
+/// `top` highest are returned as (term, term, difference) in descending order. Clusters without
+/// members yield an empty list.
+pub fn cluster_top_features(
+    data: &Array2<f32>,
+    labels: &[usize],
+    language: &IndexMap,
+    top: usize,
+) -> Vec<Vec<(String, String, f32)>> {
+    if labels.is_empty() {
+        return Vec::new();
+    }
+    let global = data.mean_axis(Axis(0)).unwrap();
+    group_by_cluster(labels)
+        .into_iter()
+        .map(|rows| {
+            if rows.is_empty() {
+                return Vec::new();
+            }
+            let means = data.select(Axis(0), &rows).mean_axis(Axis(0)).unwrap();
+            let diff = &means - &global;
+            let mut diffs: Vec<(usize, f32)> = diff.iter().cloned().enumerate().collect();
+            diffs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            diffs
+                .into_iter()
+                .take(top)
+                .map(|(i, d)| {
+                    let (r, c) = edge_index_to_term_indices(i);
+                    (language.get(r).unwrap(), language.get(c).unwrap(), d)
+                })
+                .collect()
+        })
+        .collect()
+}
+
 /// Applies statistcal feature reduction methods.
 pub fn trim_features(data: &Array2<f32>) -> Array2<f32> {
+    trim_features_with(data, SIGMA_THRESHOLD, CV_INV_THRESHOLD)
+}
+
+/// Applies statistcal feature reduction methods with the given thresholds.
+///
+/// Features with a standard deviation below `sigma_threshold` or an inverse coefficient of
+/// variation below `cv_inv_threshold` are removed. Features for which either is not finite, such
+/// as constant features, are always removed.
+pub fn trim_features_with(
+    data: &Array2<f32>,
+    sigma_threshold: f32,
+    cv_inv_threshold: f32,
+) -> Array2<f32> {
     let means = data.mean_axis(Axis(0)).unwrap();
     let stds = data.std_axis(Axis(0), 1.0);
-    let mut mask = Array1::<usize>::zeros(means.raw_dim());
-    let mut count = 1;
-    for i in 0..means.len() {
-        if stds[i] < SIGMA_THRESHOLD || means[i] / stds[i] < CV_INV_THRESHOLD {
-            continue;
+    let columns: Vec<usize> = (0..means.len())
+        .filter(|&i| {
+            let cv_inv = means[i] / stds[i];
+            stds[i].is_finite()
+                && cv_inv.is_finite()
+                && stds[i] >= sigma_threshold
+                && cv_inv >= cv_inv_threshold
+        })
+        .collect();
+    select_features(data, &columns)
+}
+
+/// Keeps the `k` features with the highest variance.
+///
+/// Unlike `trim_features`, this gives a fixed number of features, unless `data` has fewer than `k`
+/// features with a finite variance. The kept features stay in their original order.
+///
+/// The return type is of the format (trimmed data, indices of the kept columns in `data`).
+pub fn trim_features_top_k(data: &Array2<f32>, k: usize) -> (Array2<f32>, Vec<usize>) {
+    let vars = data.var_axis(Axis(0), 1.0);
+    let mut columns: Vec<usize> = (0..vars.len()).filter(|&i| vars[i].is_finite()).collect();
+    columns.sort_by(|&a, &b| {
+        vars[b]
+            .partial_cmp(&vars[a])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    columns.truncate(k);
+    columns.sort_unstable();
+    (select_features(data, &columns), columns)
+}
+
+/// Returns a feature matrix containing only the given columns of `data`, in the given order.
+///
+/// # Panics
+///
+/// Panics if a column is out of bounds.
+pub fn select_features(data: &Array2<f32>, columns: &[usize]) -> Array2<f32> {
+    data.select(Axis(1), columns)
+}
+
+/// Multiplies each feature by its weight, such as an IDF or a domain-specific importance.
+///
+/// Features with a weight of zero no longer affect distances between rows.
+///
+/// # Panics
+///
+/// Panics if the length of `weights` is not the number of columns of `data`.
+pub fn apply_feature_weights(data: &mut Array2<f32>, weights: &Array1<f32>) {
+    assert_eq!(
+        weights.len(),
+        data.ncols(),
+        "feature weights must have one weight per column"
+    );
+    *data *= weights;
+}
+
+/// Returns a random subset of `n` rows of `data`, for quick clustering of large corpora.
+///
+/// Rows are drawn without replacement and kept in their original order. All rows are returned if
+/// `data` has at most `n` rows.
+///
+/// The return type is of the format (sampled data, indices of the sampled rows in `data`).
+pub fn sample_rows<R: Rng>(data: &Array2<f32>, n: usize, rng: &mut R) -> (Array2<f32>, Vec<usize>) {
+    let rows = data.nrows();
+    let mut indices = rand::seq::index::sample(rng, rows, n.min(rows)).into_vec();
+    indices.sort_unstable();
+    (data.select(Axis(0), &indices), indices)
+}
+
+/// Scales each feature by centering it on its median and dividing by its interquartile range.
+///
+/// Unlike standardization by mean and standard deviation, this is barely affected by a few graphs
+/// with extreme edge weights. Quartiles are interpolated linearly between values. Features with an
+/// interquartile range of zero are left unchanged.
+pub fn robust_scale(data: &mut Array2<f32>) {
+    if data.nrows() == 0 {
+        return;
+    }
+    data.axis_iter_mut(Axis(1))
+        .into_par_iter()
+        .for_each(|mut column| {
+            let mut sorted = column.to_vec();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let quantile = |q: f32| {
+                let pos = q * (sorted.len() - 1) as f32;
+                let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+                sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f32)
+            };
+            let iqr = quantile(0.75) - quantile(0.25);
+            if iqr != 0.0 {
+                let median = quantile(0.5);
+                column.mapv_inplace(|x| (x - median) / iqr);
+            }
+        });
+}
+
+/// Returns the fraction of the entries of `data` that are zero, or 0.0 if it has no entries.
+///
+/// Comparing this before and after `trim_features` shows whether `sparse::reduce_dims_sparse` is
+/// worth using.
+pub fn sparsity(data: &Array2<f32>) -> f32 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    data.iter().filter(|&&v| v == 0.0).count() as f32 / data.len() as f32
+}
+
+/// Checks that every element of a feature matrix is finite.
+///
+/// # Panics
+///
+/// Panics if any element is NaN or infinite, reporting the position of the first one.
+pub fn assert_finite(data: &Array2<f32>) {
+    if let Some(((row, col), x)) = data.indexed_iter().find(|(_, x)| !x.is_finite()) {
+        panic!("feature matrix has {} at ({}, {})", x, row, col);
+    }
+}
+
+/// Groups the indices of a labeling by cluster.
+///
+/// The returned vector contains, for each label from 0 to the largest label, the indices with
+/// that label in ascending order. Labels that are not used have an empty group.
+pub fn group_by_cluster(labels: &[usize]) -> Vec<Vec<usize>> {
+    let clusters = labels.iter().max().map_or(0, |m| m + 1);
+    let mut groups = vec![Vec::new(); clusters];
+    for (i, &l) in labels.iter().enumerate() {
+        groups[l].push(i);
+    }
+    groups
+}
+
+/// Renumbers clusters in order of first appearance.
+///
+/// The cluster of the first point becomes 0, the next cluster to appear becomes 1, and so on. Two
+/// labelings describing the same partition are equal after canonicalization, regardless of the
+/// cluster ids originally assigned.
+pub fn canonicalize_labels(labels: &mut [usize]) {
+    let mut ids = HashMap::new();
+    for l in labels.iter_mut() {
+        let next = ids.len();
+        *l = *ids.entry(*l).or_insert(next);
+    }
+}
+
+/// Merges clusters whose centroids are within `min_dist` of each other.
+///
+/// The closest pair of centroids is merged repeatedly until no pair is within `min_dist`. A merged
+/// centroid is the mean of the two centroids weighted by the sizes of their clusters, which is
+/// the mean of the combined cluster. Labels are renumbered to stay contiguous, with the merged
+/// cluster taking the lower of the two labels.
+///
+/// # Panics
+///
+/// Panics if a label has no centroid.
+pub fn merge_close_clusters(centroids: &mut Vec<Array1<f32>>, labels: &mut [usize], min_dist: f32) {
+    let mut counts = vec![0; centroids.len()];
+    for &l in labels.iter() {
+        counts[l] += 1;
+    }
+    loop {
+        let closest = (0..centroids.len())
+            .flat_map(|i| (0..i).map(move |j| (j, i)))
+            .map(|(i, j)| (i, j, centroids[i].l2_dist(&centroids[j]).unwrap() as f32))
+            .filter(|&(_, _, d)| d <= min_dist)
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
+        let (keep, merge) = match closest {
+            Some((i, j, _)) => (i, j),
+            None => break,
+        };
+        let total = counts[keep] + counts[merge];
+        if total > 0 {
+            let merged = (&centroids[keep] * counts[keep] as f32
+                + &centroids[merge] * counts[merge] as f32)
+                / total as f32;
+            centroids[keep] = merged;
         }
-        mask[i] = count;
-        count += 1;
-    }
-    let f = mask.iter().filter(|&&e| e != 0).count();
-    let mut res = Array2::<f32>::zeros((data.nrows(), f));
-    for (i, m) in mask.iter().enumerate() {
-        if *m > 0 {
-            let mut slice = res.slice_mut(s![.., *m - 1]);
-            slice.assign(&data.column(i));
+        counts[keep] = total;
+        centroids.remove(merge);
+        counts.remove(merge);
+        for l in labels.iter_mut() {
+            if *l == merge {
+                *l = keep;
+            } else if *l > merge {
+                *l -= 1;
+            }
         }
     }
-    res
+}
+
+/// Embeds the verticies of a single graph, so that its terms can be clustered with any
+/// `Clustering` algorithm.
+///
+/// Each vertex is represented by its row of the adjacency matrix, holding the values of its edges
+/// to every vertex, reduced to `dims` dimensions with PCA. Verticies with similar neighborhoods
+/// get nearby embeddings. The number of dimensions is limited to the number of verticies.
+///
+/// The return type is of the format (verticies, embedding), where row `i` of the embedding belongs
+/// to vertex `i`.
+///
+/// # Panics
+///
+/// Panics if PCA fails.
+pub fn embed_vertices<T: Value>(graph: &Graph<T>, dims: usize) -> (Vec<String>, Array2<f32>) {
+    let n = graph.len();
+    let mut adjacency = Array2::zeros((n, n));
+    graph.fold_edges((), |(), (row, col, e)| {
+        adjacency[(row, col)] = e.value();
+        adjacency[(col, row)] = e.value();
+    });
+    let dims = dims.min(n);
+    let embedding = if dims == 0 {
+        Array2::zeros((n, 0))
+    } else {
+        let mut pca = petal_decomposition::Pca::new(dims);
+        pca.fit_transform(&adjacency).expect("PCA failed")
+    };
+    (graph.vertices().collect(), embedding)
+}
+
+/// Result of running the clustering pipeline with `cluster_graphs`.
+#[derive(Clone, Debug)]
+pub struct ClusterOutput {
+    /// Cluster of each graph, in the order the graphs were given.
+    pub labels: Vec<usize>,
+    /// Number of features remaining after trimming.
+    pub features: usize,
+    /// Sum of squared distances from each reduced feature vector to the mean of its cluster.
+    pub inertia: f32,
+}
+
+/// Clusters graphs by running the full pipeline: vectorization, feature trimming, PCA and the
+/// clustering algorithm `C`.
+///
+/// The number of PCA dimensions is limited to the number of graphs and remaining features. If
+/// `config.threads` is set, the pipeline runs in a thread pool of that size rather than the global
+/// one.
+///
+/// # Panics
+///
+/// Panics if PCA fails, for example when no features remain after trimming, or if the thread pool
+/// cannot be created.
+pub fn cluster_graphs<C: Clustering, T: Value, R: Rng + Send>(
+    graphs: &[Graph<T>],
+    config: &Config,
+    rng: &mut R,
+) -> ClusterOutput {
+    match config.threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("Failed to create thread pool")
+            .install(|| run_pipeline::<C, _, _>(graphs, config, rng)),
+        None => run_pipeline::<C, _, _>(graphs, config, rng),
+    }
+}
+
+/// Runs the pipeline of `cluster_graphs` in the current thread pool.
+fn run_pipeline<C: Clustering, T: Value, R: Rng>(
+    graphs: &[Graph<T>],
+    config: &Config,
+    rng: &mut R,
+) -> ClusterOutput {
+    let vectorized = vectorize(graphs);
+    let trimmed = trim_features_with(&vectorized, config.sigma_threshold, config.cv_inv_threshold);
+    drop(vectorized);
+    if cfg!(debug_assertions) {
+        assert_finite(&trimmed);
+    }
+    let dims = config.pca_dims.min(trimmed.nrows()).min(trimmed.ncols());
+    let mut pca = petal_decomposition::Pca::new(dims);
+    let reduced = pca.fit_transform(&trimmed).expect("PCA failed");
+    if cfg!(debug_assertions) {
+        assert_finite(&reduced);
+    }
+    let labels = C::cluster(&reduced, config.clusters, rng);
+    let inertia = metrics::inertia(&reduced, &labels);
+    ClusterOutput {
+        labels,
+        features: trimmed.ncols(),
+        inertia,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clustering::kmeans::KMeans,
+        construct::construct_sentence_count,
+        input::{InputFormat, NddFile},
+    };
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    fn graph(text: &str) -> Graph<u32> {
+        construct_sentence_count(&NddFile::parse(text.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn edge_index_round_trip() {
+        for row in 0..50 {
+            for col in 0..=row {
+                let i = term_indices_to_edge_index(row, col);
+                assert_eq!(edge_index_to_term_indices(i), (row, col));
+            }
+        }
+    }
+
+    #[test]
+    fn top_features_find_defining_edge() {
+        let language: IndexMap = vec!["a", "b", "c"].into_iter().collect();
+        let defining = term_indices_to_edge_index(2, 0);
+        let mut data = Array2::from_elem((4, 6), 1.0);
+        data.column_mut(defining)
+            .assign(&array![0.0, 0.0, 5.0, 5.0]);
+        let top = cluster_top_features(&data, &[0, 0, 1, 1], &language, 1);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[1], vec![("c".to_string(), "a".to_string(), 2.5)]);
+    }
+
+    #[test]
+    fn vectorize_without_self_loops() {
+        let graphs: Vec<_> = (0..4).map(|_| graph("cat dog\ncat")).collect();
+        let options = VectorizeOptions {
+            include_self_loops: false,
+            ..VectorizeOptions::default()
+        };
+        let with = vectorize(&graphs);
+        let without = vectorize_with(&graphs, &options);
+        assert_eq!(with.ncols(), without.ncols());
+        for i in 0..2 {
+            let diagonal = term_indices_to_edge_index(i, i);
+            assert!(with.column(diagonal).iter().all(|&v| v > 0.0));
+            assert!(without.column(diagonal).iter().all(|&v| v == 0.0));
+        }
+        let edge = term_indices_to_edge_index(0, 1);
+        assert_eq!(with.column(edge), without.column(edge));
+    }
+
+    #[test]
+    fn vectorize_weighted_degree() {
+        let graphs = vec![graph("cat dog\ndog bird\ncat"), graph("cat fish fish")];
+        let options = VectorizeOptions {
+            append_weighted_degree: true,
+            ..VectorizeOptions::default()
+        };
+        let plain = vectorize(&graphs);
+        let with = vectorize_with(&graphs, &options);
+        let language = corpus_language(&graphs);
+        assert_eq!(with.ncols(), plain.ncols() + language.len());
+        assert_eq!(with.slice(s![.., ..plain.ncols()]), plain);
+        for (row, g) in with.axis_iter(Axis(0)).zip(&graphs) {
+            for (i, term) in language.into_iter().enumerate() {
+                let degree = g.weighted_degree(&term).unwrap_or(0.0);
+                assert_eq!(row[plain.ncols() + i], degree);
+            }
+        }
+    }
+
+    #[test]
+    fn vectorize_sparse_matches_dense() {
+        let graphs: Vec<_> = (0..5)
+            .map(|i| graph(&format!("cat dog bird\ndog fish\nowl{} cat", i)))
+            .collect();
+        let sparse = vectorize_sparse(&graphs);
+        assert!(sparse.nnz() > 0);
+        assert_eq!(sparse, sparse::CsrMatrix::from_dense(&vectorize(&graphs)));
+    }
+
+    #[test]
+    fn select_columns() {
+        let data = array![[0.0, 1.0, 2.0, 3.0], [4.0, 5.0, 6.0, 7.0]];
+        assert_eq!(
+            select_features(&data, &[3, 1]),
+            array![[3.0, 1.0], [7.0, 5.0]]
+        );
+        assert_eq!(select_features(&data, &[]).dim(), (2, 0));
+    }
+
+    #[test]
+    fn trim_top_k() {
+        let data = array![
+            [0.0, 1.0, 5.0, f32::NAN, 10.0],
+            [0.0, 3.0, 5.0, 1.0, 0.0],
+            [0.0, 1.0, 6.0, 1.0, 10.0],
+            [0.0, 3.0, 6.0, 1.0, 0.0]
+        ];
+        let (trimmed, columns) = trim_features_top_k(&data, 2);
+        assert_eq!(columns, vec![1, 4]);
+        assert_eq!(trimmed, select_features(&data, &[1, 4]));
+        let (trimmed, columns) = trim_features_top_k(&data, 10);
+        assert_eq!(columns, vec![0, 1, 2, 4]);
+        assert_eq!(trimmed.ncols(), 4);
+    }
+
+    #[test]
+    fn trim_removes_degenerate_features() {
+        let data = array![
+            [0.0, 1.0, 5.0, f32::NAN],
+            [0.0, 1.2, 5.0, 1.0],
+            [0.0, 9.0, 5.0, 1.0],
+            [0.0, 9.2, 5.0, 1.0]
+        ];
+        let trimmed = trim_features_with(&data, 0.0, 0.0);
+        assert_eq!(trimmed.ncols(), 1);
+        assert_finite(&trimmed);
+        let labels = KMeans::cluster(&trimmed, 2, &mut Pcg64Mcg::seed_from_u64(0));
+        assert_eq!(labels[0], labels[1]);
+        assert_eq!(labels[2], labels[3]);
+        assert_ne!(labels[0], labels[2]);
+    }
+
+    #[test]
+    fn sparsity_fraction() {
+        let data = array![[0.0, 1.0, 0.0, 2.0], [0.0, 0.0, 3.0, 0.0]];
+        assert_eq!(sparsity(&data), 0.625);
+        assert_eq!(sparsity(&Array2::ones((2, 2))), 0.0);
+        assert_eq!(sparsity(&Array2::zeros((0, 3))), 0.0);
+    }
+
+    #[test]
+    fn feature_weights() {
+        let mut data = array![[0.0, 0.0, 1.0], [3.0, 4.0, 1.0], [3.0, 10.0, 2.0]];
+        apply_feature_weights(&mut data, &array![1.0, 0.0, 2.0]);
+        assert_eq!(data.column(2), array![2.0, 2.0, 4.0]);
+        let d = distance::pairwise_distances(&data, &distance::Euclidean);
+        assert_eq!(d[(0, 1)], 3.0);
+        assert_eq!(d[(1, 2)], 2.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn feature_weights_length() {
+        apply_feature_weights(&mut Array2::zeros((2, 3)), &array![1.0, 1.0]);
+    }
+
+    #[test]
+    fn sample_rows_unique() {
+        let data = Array2::from_shape_fn((50, 3), |(i, j)| (i * 3 + j) as f32);
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let (sample, indices) = sample_rows(&data, 20, rng);
+        assert_eq!(sample.dim(), (20, 3));
+        assert!(indices.windows(2).all(|w| w[0] < w[1]));
+        assert!(indices.iter().all(|&i| i < 50));
+        for (row, &i) in sample.axis_iter(Axis(0)).zip(&indices) {
+            assert_eq!(row, data.row(i));
+        }
+        let (all, indices) = sample_rows(&data, 100, rng);
+        assert_eq!(all, data);
+        assert_eq!(indices, (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn robust_scale_ignores_outlier() {
+        let clean = Array2::from_shape_fn((10, 1), |(i, _)| i as f32 + 1.0);
+        let mut outlier = clean.clone();
+        outlier[(9, 0)] = 1000.0;
+        let z_score = |data: &Array2<f32>| {
+            (data - &data.mean_axis(Axis(0)).unwrap()) / &data.std_axis(Axis(0), 0.0)
+        };
+        let (z_clean, z_outlier) = (z_score(&clean), z_score(&outlier));
+        let (mut robust_clean, mut robust_outlier) = (clean, outlier);
+        robust_scale(&mut robust_clean);
+        robust_scale(&mut robust_outlier);
+        assert!((robust_clean[(4, 0)] + 1.0 / 9.0).abs() < 1e-6);
+        for i in 0..9 {
+            assert!((robust_clean[(i, 0)] - robust_outlier[(i, 0)]).abs() < 1e-6);
+        }
+        assert!((z_clean[(0, 0)] - z_outlier[(0, 0)]).abs() > 1.0);
+    }
+
+    #[test]
+    fn robust_scale_keeps_constant_features() {
+        let mut data = array![[1.0, 2.0], [1.0, 4.0], [1.0, 6.0], [1.0, 8.0], [3.0, 10.0]];
+        robust_scale(&mut data);
+        assert_eq!(data.column(0), array![1.0, 1.0, 1.0, 1.0, 3.0]);
+        assert_eq!(data.column(1), array![-1.0, -0.5, 0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn assert_finite_rejects_nan() {
+        assert_finite(&array![[1.0, f32::NAN]]);
+    }
+
+    #[test]
+    fn groups() {
+        let groups = group_by_cluster(&[3, 0, 3, 1, 0]);
+        assert_eq!(groups, vec![vec![1, 4], vec![3], vec![], vec![0, 2]]);
+        assert!(group_by_cluster(&[]).is_empty());
+    }
+
+    #[test]
+    fn corpus_language_matches_sequential_fold() {
+        let graphs: Vec<_> = (0..12)
+            .map(|i| {
+                let text: String = (0..=i % 6)
+                    .map(|j| format!("w{} w{}\n", j, i % 4))
+                    .collect();
+                graph(&text)
+            })
+            .collect();
+        let counts = graphs
+            .iter()
+            .flat_map(|g| g.vertices())
+            .fold(HashMap::new(), |mut acc, w| {
+                *acc.entry(w).or_insert(0) += 1;
+                acc
+            });
+        for &min in &[1, 4, 9] {
+            let expected: IndexMap = counts
+                .iter()
+                .filter(|&(_, &c)| c >= min)
+                .map(|(w, _)| w.as_str())
+                .collect();
+            assert_eq!(build_corpus_language(&graphs, min), expected);
+        }
+        assert_eq!(corpus_language(&graphs), build_corpus_language(&graphs, 4));
+    }
+
+    #[test]
+    fn canonical_labels() {
+        let mut a = vec![2, 2, 0, 5, 0, 2];
+        let mut b = vec![1, 1, 5, 0, 5, 1];
+        canonicalize_labels(&mut a);
+        canonicalize_labels(&mut b);
+        assert_eq!(a, vec![0, 0, 1, 2, 1, 0]);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn merge_coincident_clusters() {
+        let mut centroids = vec![array![5.0, 5.0], array![0.0, 0.0], array![0.0, 0.0]];
+        let mut labels = vec![0, 1, 2, 1, 2, 2];
+        merge_close_clusters(&mut centroids, &mut labels, 0.5);
+        assert_eq!(centroids, vec![array![5.0, 5.0], array![0.0, 0.0]]);
+        assert_eq!(labels, vec![0, 1, 1, 1, 1, 1]);
+        merge_close_clusters(&mut centroids, &mut labels, 0.5);
+        assert_eq!(centroids.len(), 2);
+    }
+
+    #[test]
+    fn merge_uses_cluster_sizes() {
+        let mut centroids = vec![array![0.0], array![1.0], array![10.0]];
+        let mut labels = vec![0, 0, 0, 1, 2];
+        merge_close_clusters(&mut centroids, &mut labels, 2.0);
+        assert_eq!(centroids, vec![array![0.25], array![10.0]]);
+        assert_eq!(labels, vec![0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn cluster_graphs_separates_topics() {
+        let graphs = vec![
+            graph("cat dog bird\ncat dog"),
+            graph("cat dog bird"),
+            graph("dog bird cat\nbird cat"),
+            graph("cat bird\ndog cat bird"),
+            graph("car road bus\ncar road"),
+            graph("car road bus"),
+            graph("bus car\nroad car bus"),
+            graph("road bus car\nroad bus"),
+        ];
+        let config = Config {
+            clusters: 2,
+            pca_dims: 2,
+            ..Config::default()
+        };
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let output = cluster_graphs::<KMeans, _, _>(&graphs, &config, rng);
+        let labels = output.labels;
+        assert_eq!(labels.len(), graphs.len());
+        assert!(labels[..4].iter().all(|&l| l == labels[0]));
+        assert!(labels[4..].iter().all(|&l| l == labels[4]));
+        assert_ne!(labels[0], labels[4]);
+    }
+
+    #[test]
+    fn embed_two_blocks() {
+        let graph =
+            graph("cat dog bird\ncat dog\ndog bird\ncar road bus\nroad bus\ncar bus\nbird car");
+        let (vertices, embedding) = embed_vertices(&graph, 2);
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(embedding.dim(), (6, 2));
+        let labels = KMeans::cluster(&embedding, 2, &mut Pcg64Mcg::seed_from_u64(0));
+        let label = |v: &str| labels[vertices.iter().position(|w| w == v).unwrap()];
+        assert_eq!(label("cat"), label("dog"));
+        assert_eq!(label("dog"), label("bird"));
+        assert_eq!(label("car"), label("road"));
+        assert_eq!(label("road"), label("bus"));
+        assert_ne!(label("cat"), label("car"));
+    }
+
+    #[test]
+    fn cluster_graphs_in_own_pool() {
+        let graphs: Vec<_> = (0..12)
+            .map(|i| match i % 3 {
+                0 => graph("cat dog\nbird cat dog"),
+                1 => graph("car road bus\nbus car"),
+                _ => graph("sun moon star\nstar sun"),
+            })
+            .collect();
+        let config = Config {
+            clusters: 3,
+            pca_dims: 3,
+            ..Config::default()
+        };
+        let default =
+            cluster_graphs::<KMeans, _, _>(&graphs, &config, &mut Pcg64Mcg::seed_from_u64(0));
+        let single = cluster_graphs::<KMeans, _, _>(
+            &graphs,
+            &Config {
+                threads: Some(1),
+                ..config
+            },
+            &mut Pcg64Mcg::seed_from_u64(0),
+        );
+        assert_eq!(single.labels, default.labels);
+        assert_eq!(single.features, default.features);
+        assert_eq!(single.inertia, default.inertia);
+    }
 }
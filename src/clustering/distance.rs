@@ -0,0 +1,109 @@
+//! Distance measures between feature vectors.
+
+use ndarray::prelude::*;
+use ndarray_stats::DeviationExt;
+use rayon::prelude::*;
+
+/// Trait for distance measures between feature vectors.
+///
+/// Use this trait to be generic over the distance used by an algorithm.
+pub trait Metric: Sync {
+    /// Returns the distance between two vectors of the same length.
+    fn distance(&self, a: ArrayView1<f32>, b: ArrayView1<f32>) -> f32;
+}
+
+/// The Euclidean (L2) distance.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Euclidean;
+
+impl Metric for Euclidean {
+    fn distance(&self, a: ArrayView1<f32>, b: ArrayView1<f32>) -> f32 {
+        a.l2_dist(&b).unwrap() as f32
+    }
+}
+
+/// A distance chosen at runtime, such as from a configuration.
+///
+/// Unlike the types implementing `Metric` directly, the distance is dispatched on each call, so
+/// the distance can vary without changing the types of an algorithm.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Distance {
+    /// The Euclidean (L2) distance.
+    #[default]
+    Euclidean,
+    /// One minus the cosine similarity. Zero vectors are at distance 1 from every vector.
+    Cosine,
+    /// The Manhattan (L1) distance.
+    Manhattan,
+}
+
+impl Metric for Distance {
+    fn distance(&self, a: ArrayView1<f32>, b: ArrayView1<f32>) -> f32 {
+        match self {
+            Distance::Euclidean => Euclidean.distance(a, b),
+            Distance::Cosine => {
+                let norms = a.dot(&a).sqrt() * b.dot(&b).sqrt();
+                if norms == 0.0 {
+                    1.0
+                } else {
+                    1.0 - a.dot(&b) / norms
+                }
+            }
+            Distance::Manhattan => a.l1_dist(&b).unwrap(),
+        }
+    }
+}
+
+/// Computes the distance between each pair of rows of `data`.
+///
+/// The result is a symmetric `n×n` matrix, where `n` is the number of rows. The distance is
+/// computed once for each unordered pair of rows, with the rows handled in parallel.
+pub fn pairwise_distances(data: &Array2<f32>, metric: &impl Metric) -> Array2<f32> {
+    let n = data.nrows();
+    let lower: Vec<Vec<f32>> = (0..n)
+        .into_par_iter()
+        .map(|i| {
+            (0..i)
+                .map(|j| metric.distance(data.row(i), data.row(j)))
+                .collect()
+        })
+        .collect();
+    let mut res = Array2::zeros((n, n));
+    for (i, row) in lower.iter().enumerate() {
+        for (j, &d) in row.iter().enumerate() {
+            res[(i, j)] = d;
+            res[(j, i)] = d;
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairwise_euclidean() {
+        let data = array![[0.0, 0.0], [3.0, 4.0], [0.0, 1.0]];
+        let d = pairwise_distances(&data, &Euclidean);
+        let expected = array![
+            [0.0, 5.0, 1.0],
+            [5.0, 0.0, 18.0f32.sqrt()],
+            [1.0, 18.0f32.sqrt(), 0.0]
+        ];
+        assert!(d.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-6));
+        assert_eq!(d, d.t());
+        assert!(d.diag().iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn distance() {
+        let (a, b) = (array![3.0, 0.0], array![1.0, 1.0]);
+        let d = |metric: Distance| metric.distance(a.view(), b.view());
+        assert!((d(Distance::Euclidean) - 5.0f32.sqrt()).abs() < 1e-6);
+        assert!((d(Distance::Cosine) - (1.0 - 0.5f32.sqrt())).abs() < 1e-6);
+        assert_eq!(d(Distance::Manhattan), 3.0);
+        let zero = Array1::zeros(2);
+        assert_eq!(Distance::Cosine.distance(zero.view(), b.view()), 1.0);
+    }
+}
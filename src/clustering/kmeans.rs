@@ -1,16 +1,105 @@
 //! Implementation of Kmeans using Kmeans++
 
-use crate::clustering::Clustering;
+use crate::clustering::{
+    distance::{Distance, Metric},
+    Clustering,
+};
 use ndarray::prelude::*;
 use ndarray_stats::DeviationExt;
-use rand::{distributions::weighted::WeightedIndex, distributions::Distribution, Rng};
+use rand::{
+    distributions::weighted::WeightedIndex, distributions::Distribution, seq::SliceRandom, Rng,
+};
 use rayon::prelude::*;
+use std::time::Instant;
+
+/// Number of rows summed by each task when updating the means.
+const CHUNK_ROWS: usize = 256;
+/// Number of new rows assigned together by `KMeans::partial_fit` before the means are updated.
+const PARTIAL_FIT_BATCH: usize = 100;
 
 /// Kmeans implementation
 #[allow(missing_debug_implementations, missing_copy_implementations)]
 pub struct KMeans;
 
-fn kmeans_pp<R: Rng>(data: &Array2<f32>, clusters: usize, rng: &mut R) -> Vec<Array1<f32>> {
+/// Convergence criterion for `KMeans`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopOn {
+    /// Stop once no mean moves further than this distance in an iteration.
+    CentroidMove(f32),
+    /// Stop once at most this fraction of the rows change cluster in an iteration.
+    LabelChange(f32),
+}
+
+/// Parameters for `KMeans`.
+#[derive(Clone, Copy, Debug)]
+pub struct KMeansConfig {
+    /// Number of clusters to produce.
+    pub clusters: usize,
+    /// Maximum number of iterations for each initialization.
+    pub max_iter: usize,
+    /// When iteration stops before `max_iter` is reached.
+    pub stop_on: StopOn,
+    /// Number of independent initializations to run. The result with the lowest inertia is kept.
+    pub n_init: usize,
+    /// Time after which no further iterations or initializations are started. The best labeling
+    /// found so far is then returned, even if it has not converged.
+    pub deadline: Option<Instant>,
+    /// Distance used to assign rows to means and to choose initial means. Means are still updated
+    /// to the mean of their rows, so only `Distance::Euclidean` minimizes the inertia exactly.
+    pub metric: Distance,
+}
+
+impl KMeansConfig {
+    /// Creates a configuration for the given number of clusters with default parameters.
+    pub fn new(clusters: usize) -> Self {
+        KMeansConfig {
+            clusters,
+            max_iter: 20,
+            stop_on: StopOn::CentroidMove(1e-4),
+            n_init: 1,
+            deadline: None,
+            metric: Distance::Euclidean,
+        }
+    }
+
+    /// Returns `true` if the deadline has passed.
+    pub(crate) fn past_deadline(&self) -> bool {
+        matches!(self.deadline, Some(d) if Instant::now() >= d)
+    }
+
+    /// Returns the number of initializations to run, which is at least one.
+    ///
+    /// Once the deadline has passed, further initializations are skipped.
+    pub(crate) fn inits(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..std::cmp::max(self.n_init, 1)).take_while(move |&i| i == 0 || !self.past_deadline())
+    }
+}
+
+/// Result of running `KMeans`.
+#[derive(Clone, Debug)]
+pub struct KMeansResult {
+    /// Cluster of each row of the data.
+    pub labels: Vec<usize>,
+    /// Mean of each cluster.
+    pub centroids: Vec<Array1<f32>>,
+    /// Sum of squared distances from each row to the mean of its cluster, under the configured
+    /// metric.
+    pub inertia: f32,
+    /// Number of iterations run.
+    pub iterations: usize,
+    /// Total distance moved by the means and number of rows that changed cluster in each
+    /// iteration, for diagnosing convergence.
+    ///
+    /// The return type is of the format (movement, reassignments).
+    pub history: Vec<(f32, usize)>,
+}
+
+fn kmeans_pp<R: Rng>(
+    data: &Array2<f32>,
+    clusters: usize,
+    metric: Distance,
+    rng: &mut R,
+) -> Vec<Array1<f32>> {
     let mut means: Vec<Array1<f32>> = Vec::with_capacity(clusters);
     let mut min_sq_dist = Array1::from_elem(data.nrows(), f32::INFINITY);
     let init_mean = rng.gen_range(0, data.nrows());
@@ -20,64 +109,548 @@ fn kmeans_pp<R: Rng>(data: &Array2<f32>, clusters: usize, rng: &mut R) -> Vec<Ar
         ndarray::Zip::from(data.axis_iter(Axis(0)))
             .and(&mut min_sq_dist)
             .par_apply(|v, msd| {
-                let new_sd = v.sq_l2_dist(new_mean).unwrap();
+                let new_sd = sq_distance(metric, v, new_mean);
                 if new_sd < *msd {
                     *msd = new_sd;
                 }
             });
-        let index = WeightedIndex::new(&min_sq_dist).unwrap().sample(rng);
-        //let index = min_sq_dist
-        //    .iter()
-        //    .enumerate()
-        //    .max_by(|(_, d1), (_, d2)| d1.partial_cmp(d2).unwrap_or(std::cmp::Ordering::Equal))
-        //    .unwrap()
-        //    .0;
+        // All weights are zero if there are fewer distinct rows than clusters.
+        let index = match WeightedIndex::new(&min_sq_dist) {
+            Ok(w) => w.sample(rng),
+            Err(_) => rng.gen_range(0, data.nrows()),
+        };
         means.push(data.row(index).to_owned());
     }
     means
 }
 
-impl Clustering for KMeans {
-    fn cluster<R: Rng>(vectors: &Array2<f32>, mut clusters: usize, rng: &mut R) -> Vec<usize> {
-        let mut cluster_map = Array1::zeros(vectors.nrows());
-        clusters = std::cmp::min(clusters, vectors.nrows());
-        if clusters == 0 {
-            return cluster_map.to_vec();
+/// Returns the squared distance between `v` and `mean` under `metric`.
+///
+/// The Euclidean distance is computed without a square root, as it is the most common.
+fn sq_distance(metric: Distance, v: ArrayView1<f32>, mean: &Array1<f32>) -> f32 {
+    match metric {
+        Distance::Euclidean => v.sq_l2_dist(mean).unwrap(),
+        _ => metric.distance(v, mean.view()).powi(2),
+    }
+}
+
+/// Returns the index of the mean nearest to `v` under `metric` and the squared distance to it.
+///
+/// Ties are broken in favour of the mean with the lowest index.
+///
+/// # Panics
+///
+/// Panics if a distance is not finite, as happens when the data contains NaN or infinite values.
+fn nearest(v: ArrayView1<f32>, means: &[Array1<f32>], metric: Distance) -> (usize, f32) {
+    let mut best = (0, f32::INFINITY);
+    for (i, m) in means.iter().enumerate() {
+        let sd = sq_distance(metric, v, m);
+        assert!(sd.is_finite(), "non-finite distance to mean {}", i);
+        if sd < best.1 {
+            best = (i, sd);
         }
-        let mut means = kmeans_pp(&vectors, clusters, rng);
-        let cols = vectors.ncols();
-        let rows = vectors.nrows();
-        for n in 0..20 {
-            println!("Iter {}", n);
-            ndarray::Zip::from(vectors.axis_iter(Axis(0)))
-                .and(&mut cluster_map)
-                .par_apply(|v, c| {
-                    *c = means
-                        .iter()
-                        .enumerate()
-                        .map(|(i, m)| (i, v.sq_l2_dist(m).unwrap()))
-                        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
-                        .unwrap()
-                        .0
-                });
-            //(0..clusters).for_each(|c| {
-            //    if !cluster_map.contains(&c) {
-            //        let index = rng.gen_range(0, cluster_map.len());
-            //        cluster_map[index] = c;
-            //    }
-            //});
-            means.par_iter_mut().enumerate().for_each(|(i, m)| {
-                *m = ndarray::Zip::from(vectors.axis_iter(Axis(0)))
-                    .and(&cluster_map)
-                    .into_par_iter()
-                    .filter(|(_, c)| **c == i)
-                    .map(|(v, _)| v)
-                    .fold(|| Array1::zeros(cols), |s, v| s + v)
-                    .reduce(|| Array1::zeros(cols), |s, sp| s + sp)
-                    / (rows as f32);
-            });
-            println!("{:?}", cluster_map);
+    }
+    best
+}
+
+/// Assigns each row to its nearest mean, as found by `nearest`.
+///
+/// Returns the sum of squared distances and the number of rows that changed cluster.
+fn assign(
+    data: &Array2<f32>,
+    means: &[Array1<f32>],
+    metric: Distance,
+    cluster_map: &mut Array1<usize>,
+) -> (f32, usize) {
+    let mut sq_dists = Array1::zeros(data.nrows());
+    let mut changed = Array1::<usize>::zeros(data.nrows());
+    ndarray::Zip::from(data.axis_iter(Axis(0)))
+        .and(cluster_map)
+        .and(&mut sq_dists)
+        .and(&mut changed)
+        .par_apply(|v, c, d, ch| {
+            let (i, sd) = nearest(v, means, metric);
+            *ch = (*c != i) as usize;
+            *c = i;
+            *d = sd;
+        });
+    (sq_dists.sum(), changed.sum())
+}
+
+/// Moves each mean to the mean of the rows assigned to it.
+///
+/// The return type is of the format (largest distance moved, total distance moved).
+///
+/// Rows are summed in parallel in chunks of `CHUNK_ROWS`, and the chunk sums are added in order,
+/// so the result does not depend on the number of threads.
+fn update(
+    data: &Array2<f32>,
+    cluster_map: &Array1<usize>,
+    means: &mut [Array1<f32>],
+) -> (f32, f32) {
+    let (rows, cols, clusters) = (data.nrows(), data.ncols(), means.len());
+    let starts: Vec<usize> = (0..rows).step_by(CHUNK_ROWS).collect();
+    let chunks: Vec<(Array2<f32>, Vec<usize>)> = starts
+        .into_par_iter()
+        .map(|start| {
+            let end = std::cmp::min(start + CHUNK_ROWS, rows);
+            let mut sums = Array2::zeros((clusters, cols));
+            let mut counts = vec![0; clusters];
+            for r in start..end {
+                let c = cluster_map[r];
+                let mut sum = sums.row_mut(c);
+                sum += &data.row(r);
+                counts[c] += 1;
+            }
+            (sums, counts)
+        })
+        .collect();
+    let mut sums = Array2::zeros((clusters, cols));
+    let mut counts = vec![0; clusters];
+    for (s, n) in chunks {
+        sums += &s;
+        counts.iter_mut().zip(n).for_each(|(a, b)| *a += b);
+    }
+    let mut max_moved = 0.0f32;
+    let mut total_moved = 0.0f32;
+    for (i, m) in means.iter_mut().enumerate() {
+        // An empty cluster keeps its previous mean.
+        if counts[i] == 0 {
+            continue;
+        }
+        let new = &sums.row(i) / (counts[i] as f32);
+        let moved = new.l2_dist(m).unwrap() as f32;
+        max_moved = max_moved.max(moved);
+        total_moved += moved;
+        *m = new;
+    }
+    (max_moved, total_moved)
+}
+
+/// Runs Lloyd's algorithm from the given initial means.
+fn lloyd(data: &Array2<f32>, mut means: Vec<Array1<f32>>, config: &KMeansConfig) -> KMeansResult {
+    let mut cluster_map = Array1::zeros(data.nrows());
+    let mut iterations = 0;
+    let mut history = Vec::new();
+    while iterations < config.max_iter && !config.past_deadline() {
+        let (_, changed) = assign(data, &means, config.metric, &mut cluster_map);
+        // Every row is considered reassigned in the first iteration, as it has no prior cluster.
+        if let StopOn::LabelChange(fraction) = config.stop_on {
+            if iterations > 0 && changed as f32 <= fraction * data.nrows() as f32 {
+                break;
+            }
+        }
+        let (moved, total_moved) = update(data, &cluster_map, &mut means);
+        iterations += 1;
+        history.push((total_moved, changed));
+        if let StopOn::CentroidMove(distance) = config.stop_on {
+            if moved <= distance {
+                break;
+            }
         }
+    }
+    let (inertia, _) = assign(data, &means, config.metric, &mut cluster_map);
+    KMeansResult {
+        labels: cluster_map.to_vec(),
+        centroids: means,
+        inertia,
+        iterations,
+        history,
+    }
+}
+
+impl KMeans {
+    /// Clusters the rows of `data` according to `config`.
+    ///
+    /// Runs `config.n_init` initializations with Kmeans++ and returns the result with the lowest
+    /// inertia. The result depends only on `data`, `config`, and the state of `rng`, not on the
+    /// number of threads, so a seeded random number generator gives reproducible results.
+    ///
+    /// The number of clusters is limited to the number of rows. A row equally close to several
+    /// means is assigned to the one with the lowest index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` contains NaN or infinite values.
+    pub fn fit<R: Rng>(data: &Array2<f32>, config: &KMeansConfig, rng: &mut R) -> KMeansResult {
+        let clusters = std::cmp::min(config.clusters, data.nrows());
+        if clusters == 0 {
+            return KMeansResult {
+                labels: vec![0; data.nrows()],
+                centroids: Vec::new(),
+                inertia: 0.0,
+                iterations: 0,
+                history: Vec::new(),
+            };
+        }
+        config
+            .inits()
+            .map(|_| lloyd(data, kmeans_pp(data, clusters, config.metric, rng), config))
+            .min_by(|a, b| {
+                a.inertia
+                    .partial_cmp(&b.inertia)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap()
+    }
+
+    /// Clusters the rows of `data`, starting from the given centroids instead of Kmeans++.
+    ///
+    /// Each row of `init` is the initial mean of one cluster, so `config.clusters` and
+    /// `config.n_init` are ignored. This allows warm-starting from a previous clustering.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `init` has no rows, if it does not have the same number of columns as `data`, or
+    /// if either contains NaN or infinite values.
+    pub fn cluster_from_centroids(
+        data: &Array2<f32>,
+        init: &Array2<f32>,
+        config: &KMeansConfig,
+    ) -> KMeansResult {
+        assert!(init.nrows() > 0, "no centroids to start clustering from");
+        assert_eq!(
+            init.ncols(),
+            data.ncols(),
+            "centroids must have the same number of columns as the data"
+        );
+        let means = init.axis_iter(Axis(0)).map(|r| r.to_owned()).collect();
+        lloyd(data, means, config)
+    }
+}
+
+impl KMeans {
+    /// Assigns each row of `data` to the nearest of the given centroids under `metric`, such as
+    /// those of a `KMeansResult`.
+    ///
+    /// This allows clustering a sample of the data and then labeling the rest, using the `metric`
+    /// of the `KMeansConfig` the centroids were found with. A row equally close to several
+    /// centroids is assigned to the one with the lowest index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `centroids` is empty, or if `data` or `centroids` contain NaN or infinite values.
+    pub fn predict(data: &Array2<f32>, centroids: &[Array1<f32>], metric: Distance) -> Vec<usize> {
+        assert!(!centroids.is_empty(), "no centroids to assign rows to");
+        let mut cluster_map = Array1::zeros(data.nrows());
+        assign(data, centroids, metric, &mut cluster_map);
         cluster_map.to_vec()
     }
+
+    /// Updates a clustering with new rows, without clustering all the rows again.
+    ///
+    /// The new rows are shuffled and taken in mini-batches. Each batch is assigned to the nearest
+    /// centroids under `metric`, and each centroid is then moved towards its new rows with
+    /// a step size of one over the number of rows assigned to it so far, counting the existing
+    /// labels. The labels of the new rows are appended to `result.labels`, in order, and their
+    /// squared distances are added to `result.inertia`.
+    ///
+    /// Returns the mean squared distance from the new rows to their centroids when assigned. A
+    /// value much larger than the mean for the existing rows, `inertia / labels.len()`, indicates
+    /// that the new rows come from a region the clustering does not cover.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `result` has no centroids, if `new_rows` does not have the same number of columns
+    /// as the centroids, or if either contains NaN or infinite values.
+    pub fn partial_fit<R: Rng>(
+        result: &mut KMeansResult,
+        new_rows: &Array2<f32>,
+        metric: Distance,
+        rng: &mut R,
+    ) -> f32 {
+        assert!(
+            !result.centroids.is_empty(),
+            "no centroids to assign rows to"
+        );
+        assert_eq!(
+            new_rows.ncols(),
+            result.centroids[0].len(),
+            "rows must have the same number of columns as the centroids"
+        );
+        let mut counts = vec![0usize; result.centroids.len()];
+        for &l in &result.labels {
+            counts[l] += 1;
+        }
+        let mut order: Vec<usize> = (0..new_rows.nrows()).collect();
+        order.shuffle(rng);
+        let mut labels = vec![0; new_rows.nrows()];
+        let mut sq_dist = 0.0;
+        for batch in order.chunks(PARTIAL_FIT_BATCH) {
+            let rows = new_rows.select(Axis(0), batch);
+            let mut cluster_map = Array1::zeros(batch.len());
+            sq_dist += assign(&rows, &result.centroids, metric, &mut cluster_map).0;
+            for ((row, &i), &c) in rows.axis_iter(Axis(0)).zip(batch).zip(&cluster_map) {
+                counts[c] += 1;
+                let delta = &row - &result.centroids[c];
+                result.centroids[c].scaled_add(1.0 / counts[c] as f32, &delta);
+                labels[i] = c;
+            }
+        }
+        result.labels.extend(labels);
+        result.inertia += sq_dist;
+        sq_dist / new_rows.nrows().max(1) as f32
+    }
+}
+
+impl Clustering for KMeans {
+    fn cluster<R: Rng>(vectors: &Array2<f32>, clusters: usize, rng: &mut R) -> Vec<usize> {
+        KMeans::fit(vectors, &KMeansConfig::new(clusters), rng).labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    /// Generates six tight blobs in two groups, which Kmeans++ occasionally covers with too few
+    /// means in one group.
+    fn blobs(rng: &mut impl Rng) -> Array2<f32> {
+        let centers = [
+            [0.0, 0.0],
+            [3.0, 0.0],
+            [0.0, 3.0],
+            [3.0, 3.0],
+            [20.0, 0.0],
+            [20.0, 3.0],
+        ];
+        let mut data = Array2::zeros((centers.len() * 20, 2));
+        for (i, mut row) in data.axis_iter_mut(Axis(0)).enumerate() {
+            let c = centers[i / 20];
+            row[0] = c[0] + rng.gen_range(-0.5, 0.5);
+            row[1] = c[1] + rng.gen_range(-0.5, 0.5);
+        }
+        data
+    }
+
+    fn stack_centroids(centroids: &[Array1<f32>]) -> Array2<f32> {
+        Array2::from_shape_fn((centroids.len(), centroids[0].len()), |(i, j)| {
+            centroids[i][j]
+        })
+    }
+
+    #[test]
+    fn n_init_avoids_local_optima() {
+        let data = blobs(&mut Pcg64Mcg::seed_from_u64(0));
+        // The optimal clustering has an inertia of around 20; a clustering merging two blobs has
+        // an inertia of over 50.
+        let single = KMeansConfig::new(6);
+        let bad_runs = (0..30)
+            .map(|seed| KMeans::fit(&data, &single, &mut Pcg64Mcg::seed_from_u64(seed)))
+            .filter(|r| r.inertia > 50.0)
+            .count();
+        assert!(bad_runs > 0);
+        let restarts = KMeansConfig {
+            n_init: 10,
+            ..single
+        };
+        for seed in 0..5 {
+            let result = KMeans::fit(&data, &restarts, &mut Pcg64Mcg::seed_from_u64(seed));
+            assert!(result.inertia < 25.0);
+        }
+    }
+
+    #[test]
+    fn true_centroids_converge_immediately() {
+        let data = blobs(&mut Pcg64Mcg::seed_from_u64(0));
+        let mut init = Array2::zeros((6, 2));
+        for (i, row) in data.axis_iter(Axis(0)).enumerate() {
+            let mut mean = init.row_mut(i / 20);
+            mean += &(&row / 20.0);
+        }
+        let result = KMeans::cluster_from_centroids(&data, &init, &KMeansConfig::new(6));
+        assert_eq!(result.iterations, 1);
+        let expected: Vec<usize> = (0..data.nrows()).map(|i| i / 20).collect();
+        assert_eq!(result.labels, expected);
+        for (c, i) in result.centroids.iter().zip(init.axis_iter(Axis(0))) {
+            assert!(c.l2_dist(&i).unwrap() < 1e-4);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn centroids_must_match_columns() {
+        let data = blobs(&mut Pcg64Mcg::seed_from_u64(0));
+        KMeans::cluster_from_centroids(&data, &Array2::zeros((6, 3)), &KMeansConfig::new(6));
+    }
+
+    #[test]
+    #[should_panic(expected = "no centroids to start clustering from")]
+    fn centroids_must_not_be_empty() {
+        let data = blobs(&mut Pcg64Mcg::seed_from_u64(0));
+        KMeans::cluster_from_centroids(&data, &Array2::zeros((0, 2)), &KMeansConfig::new(6));
+    }
+
+    #[test]
+    fn partial_fit() {
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let centers = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]];
+        let around = |center: [f32; 2], n, rng: &mut Pcg64Mcg| {
+            Array2::from_shape_fn((n, 2), |(_, j)| center[j] + rng.gen_range(-0.5, 0.5))
+        };
+        let mut data = Array2::zeros((0, 2));
+        for &c in &centers {
+            data = ndarray::stack![Axis(0), data, around(c, 20, rng)];
+        }
+        let mut result = KMeans::fit(&data, &KMeansConfig::new(3), rng);
+        let baseline = result.inertia / result.labels.len() as f32;
+        let origin =
+            KMeans::predict(&array![[0.0, 0.0]], &result.centroids, Distance::Euclidean)[0];
+        let before = result.centroids[origin].clone();
+
+        let near = around(centers[0], 30, rng);
+        let drift = KMeans::partial_fit(&mut result, &near, Distance::Euclidean, rng);
+        assert_eq!(result.labels.len(), 90);
+        assert!(result.labels[60..].iter().all(|&l| l == origin));
+        assert!(result.centroids[origin].l2_dist(&before).unwrap() < 0.2);
+        assert!(drift < 2.0 * baseline);
+
+        let far = around([50.0, 50.0], 30, rng);
+        let drift = KMeans::partial_fit(&mut result, &far, Distance::Euclidean, rng);
+        assert_eq!(result.labels.len(), 120);
+        assert!(drift > 100.0 * baseline);
+    }
+
+    #[test]
+    fn metric_changes_assignments() {
+        let data = array![[5.8, 1.5], [3.0, 0.5]];
+        let init = array![[10.0, 0.0], [1.0, 1.0]];
+        let labels = |metric| {
+            let config = KMeansConfig {
+                max_iter: 0,
+                metric,
+                ..KMeansConfig::new(2)
+            };
+            KMeans::cluster_from_centroids(&data, &init, &config).labels
+        };
+        assert_eq!(labels(Distance::Euclidean), [0, 1]);
+        assert_eq!(labels(Distance::Manhattan), [1, 1]);
+        assert_eq!(labels(Distance::Cosine), [0, 0]);
+        let centroids: Vec<_> = init.axis_iter(Axis(0)).map(|r| r.to_owned()).collect();
+        for &metric in &[Distance::Euclidean, Distance::Manhattan, Distance::Cosine] {
+            assert_eq!(KMeans::predict(&data, &centroids, metric), labels(metric));
+        }
+    }
+
+    #[test]
+    fn ties_go_to_lowest_index() {
+        let data = array![[1.0, 0.0], [0.0, 1.0]];
+        let means = [array![0.0, 0.0], array![2.0, 0.0], array![1.0, 1.0]];
+        let mut cluster_map = Array1::from(vec![2, 2]);
+        let (inertia, changed) = assign(&data, &means, Distance::Euclidean, &mut cluster_map);
+        assert_eq!(cluster_map.to_vec(), [0, 0]);
+        assert_eq!((inertia, changed), (2.0, 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "non-finite distance")]
+    fn non_finite_distance_panics() {
+        let data = array![[0.0, 0.0], [f32::NAN, 1.0], [5.0, 5.0]];
+        let init = array![[0.0, 0.0], [5.0, 5.0]];
+        KMeans::cluster_from_centroids(&data, &init, &KMeansConfig::new(2));
+    }
+
+    #[test]
+    fn history() {
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let centers = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]];
+        let data = Array2::from_shape_fn((60, 2), |(i, j)| {
+            centers[i % 3][j] + rng.gen_range(-0.5, 0.5)
+        });
+        let config = KMeansConfig {
+            max_iter: 100,
+            stop_on: StopOn::CentroidMove(0.0),
+            ..KMeansConfig::new(3)
+        };
+        let result = KMeans::fit(&data, &config, rng);
+        assert_eq!(result.history.len(), result.iterations);
+        assert!(result.iterations > 1);
+        assert!(result.history.windows(2).all(|w| w[1].0 <= w[0].0));
+        assert!(result.history[0].1 > 0);
+        assert_eq!(*result.history.last().unwrap(), (0.0, 0));
+    }
+
+    #[test]
+    fn deadline_stops_early() {
+        let data = blobs(&mut Pcg64Mcg::seed_from_u64(0));
+        let config = KMeansConfig {
+            n_init: 1000,
+            max_iter: 1000,
+            stop_on: StopOn::CentroidMove(0.0),
+            deadline: Some(Instant::now()),
+            ..KMeansConfig::new(6)
+        };
+        let start = Instant::now();
+        let result = KMeans::fit(&data, &config, &mut Pcg64Mcg::seed_from_u64(0));
+        assert!(start.elapsed().as_secs() < 1);
+        assert_eq!(result.iterations, 0);
+        assert_eq!(result.labels.len(), data.nrows());
+        assert!(result.labels.iter().all(|&l| l < 6));
+        assert_eq!(result.centroids.len(), 6);
+    }
+
+    #[test]
+    fn deterministic_for_seed() {
+        let rng = &mut Pcg64Mcg::seed_from_u64(1);
+        let data = Array2::from_shape_fn((3 * CHUNK_ROWS + 7, 3), |_| rng.gen_range(-1.0, 1.0));
+        let config = KMeansConfig {
+            n_init: 2,
+            ..KMeansConfig::new(5)
+        };
+        let run = |threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| KMeans::fit(&data, &config, &mut Pcg64Mcg::seed_from_u64(0)))
+        };
+        let single = run(1);
+        for &threads in &[1, 4] {
+            let result = run(threads);
+            assert_eq!(result.labels, single.labels);
+            assert_eq!(result.centroids, single.centroids);
+            assert_eq!(result.inertia, single.inertia);
+        }
+        let labels = |seed| KMeans::cluster(&data, 5, &mut Pcg64Mcg::seed_from_u64(seed));
+        assert_eq!(labels(5), labels(5));
+    }
+
+    #[test]
+    fn predict_matches_fit() {
+        let data = blobs(&mut Pcg64Mcg::seed_from_u64(0));
+        let result = KMeans::fit(
+            &data,
+            &KMeansConfig::new(6),
+            &mut Pcg64Mcg::seed_from_u64(0),
+        );
+        assert_eq!(
+            KMeans::predict(&data, &result.centroids, Distance::Euclidean),
+            result.labels
+        );
+        let labels = KMeans::predict(
+            &array![[19.0, 0.5], [0.0, 0.0]],
+            &result.centroids,
+            Distance::Euclidean,
+        );
+        assert_eq!(labels[0], result.labels[80]);
+        assert_eq!(labels[1], result.labels[0]);
+    }
+
+    #[test]
+    fn stop_on_label_change() {
+        let data = blobs(&mut Pcg64Mcg::seed_from_u64(0));
+        let config = KMeansConfig {
+            max_iter: 100,
+            stop_on: StopOn::LabelChange(0.0),
+            ..KMeansConfig::new(6)
+        };
+        let result = KMeans::fit(&data, &config, &mut Pcg64Mcg::seed_from_u64(0));
+        assert!(result.iterations < config.max_iter);
+        let init = stack_centroids(&result.centroids);
+        let next = KMeans::cluster_from_centroids(&data, &init, &config);
+        assert_eq!(next.labels, result.labels);
+        assert_eq!(next.iterations, 1);
+    }
 }
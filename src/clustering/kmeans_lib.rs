@@ -1,16 +1,97 @@
 //! Kmeans using external library.
 
-use crate::clustering::Clustering;
+use crate::clustering::{
+    kmeans::{KMeansConfig, KMeansResult, StopOn},
+    metrics, Clustering,
+};
 use ndarray::prelude::*;
 use rand::Rng;
 
 /// Kmeans using external library.
+///
+/// Takes the same configuration as `kmeans::KMeans`. `max_iter` and the distance of
+/// `StopOn::CentroidMove` are passed to the library as its maximum number of iterations and
+/// minimum delta. The library does not support `StopOn::LabelChange`, so iteration then runs until
+/// convergence or `max_iter`. Each of the `n_init` initializations is seeded from the given random
+/// number generator, so results are reproducible. The library cannot be interrupted, so `deadline`
+/// is only checked between initializations. The library always uses the Euclidean distance, so
+/// `metric` is ignored.
 #[allow(missing_debug_implementations, missing_copy_implementations)]
 pub struct KMeans;
 
+impl KMeans {
+    /// Clusters the rows of `data` according to `config`.
+    ///
+    /// Runs `config.n_init` initializations and returns the result with the lowest inertia. The
+    /// library does not report the iterations run, so `iterations` is always 0 and `history` is
+    /// empty.
+    pub fn fit<R: Rng>(data: &Array2<f32>, config: &KMeansConfig, rng: &mut R) -> KMeansResult {
+        let clusters = std::cmp::min(config.clusters, data.nrows());
+        if clusters == 0 {
+            return KMeansResult {
+                labels: vec![0; data.nrows()],
+                centroids: Vec::new(),
+                inertia: 0.0,
+                iterations: 0,
+                history: Vec::new(),
+            };
+        }
+        config
+            .inits()
+            .map(|_| {
+                let rkm_config = rkm::Config::from(
+                    Some(rng.gen()),
+                    Some(config.max_iter as u64),
+                    match config.stop_on {
+                        StopOn::CentroidMove(distance) => Some(distance),
+                        StopOn::LabelChange(_) => None,
+                    },
+                );
+                let (centroids, labels) =
+                    rkm::kmeans_lloyd_with_config(&data.view(), clusters, &rkm_config);
+                KMeansResult {
+                    inertia: metrics::inertia(data, &labels),
+                    centroids: centroids.axis_iter(Axis(0)).map(|c| c.to_owned()).collect(),
+                    labels,
+                    iterations: 0,
+                    history: Vec::new(),
+                }
+            })
+            .min_by(|a, b| {
+                a.inertia
+                    .partial_cmp(&b.inertia)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap()
+    }
+}
+
 impl Clustering for KMeans {
-    fn cluster<R: Rng>(data: &Array2<f32>, clusters: usize, _rng: &mut R) -> Vec<usize> {
-        let (_, clusters) = rkm::kmeans_lloyd(&data.view(), clusters);
-        clusters
+    fn cluster<R: Rng>(data: &Array2<f32>, clusters: usize, rng: &mut R) -> Vec<usize> {
+        KMeans::fit(data, &KMeansConfig::new(clusters), rng).labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::{canonicalize_labels, kmeans};
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    #[test]
+    fn matches_kmeans() {
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let centers = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]];
+        let data = Array2::from_shape_fn((30, 2), |(i, j)| {
+            centers[i % 3][j] + rng.gen_range(-0.5, 0.5)
+        });
+        let config = KMeansConfig::new(3);
+        let mut lib = KMeans::fit(&data, &config, rng).labels;
+        let mut own = kmeans::KMeans::fit(&data, &config, rng).labels;
+        canonicalize_labels(&mut lib);
+        canonicalize_labels(&mut own);
+        assert_eq!(lib, own);
+        assert_eq!(lib[..3], [0, 1, 2]);
     }
 }
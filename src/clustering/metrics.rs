@@ -0,0 +1,540 @@
+//! Measures for evaluating and comparing clusterings.
+
+use crate::{
+    clustering::{
+        kmeans::{KMeans, KMeansConfig},
+        Clustering, Value,
+    },
+    graph::Graph,
+};
+use ndarray::prelude::*;
+use ndarray_stats::DeviationExt;
+use rand::Rng;
+use std::collections::HashMap;
+
+/// Fraction of rows drawn for each subsample in `bootstrap_stability`.
+const SUBSAMPLE_FRACTION: f32 = 0.8;
+
+/// Returns the number of unordered pairs that can be drawn from `n` items.
+fn pairs(n: usize) -> f64 {
+    (n * n.saturating_sub(1)) as f64 / 2.0
+}
+
+/// Computes the adjusted Rand index between two labelings of the same points.
+///
+/// The result is 1.0 for identical partitions (up to renaming of the labels) and close to 0.0 for
+/// independent ones. It can be negative for partitions that agree less than expected by chance.
+///
+/// # Panics
+///
+/// Panics if the labelings have different lengths.
+pub fn adjusted_rand_index(a: &[usize], b: &[usize]) -> f32 {
+    assert_eq!(a.len(), b.len(), "labelings must have the same length");
+    let mut joint = HashMap::new();
+    let mut a_counts = HashMap::new();
+    let mut b_counts = HashMap::new();
+    for (&x, &y) in a.iter().zip(b) {
+        *joint.entry((x, y)).or_insert(0) += 1;
+        *a_counts.entry(x).or_insert(0) += 1;
+        *b_counts.entry(y).or_insert(0) += 1;
+    }
+    let index: f64 = joint.values().map(|&n| pairs(n)).sum();
+    let a_pairs: f64 = a_counts.values().map(|&n| pairs(n)).sum();
+    let b_pairs: f64 = b_counts.values().map(|&n| pairs(n)).sum();
+    let expected = a_pairs * b_pairs / pairs(a.len()).max(1.0);
+    let max = (a_pairs + b_pairs) / 2.0;
+    if max == expected {
+        // Both labelings are trivial (all one cluster or all singletons), so they can only agree.
+        return 1.0;
+    }
+    ((index - expected) / (max - expected)) as f32
+}
+
+/// Computes the sum of squared distances from each point to the mean of its cluster.
+///
+/// # Panics
+///
+/// Panics if `labels` does not have a label for each row of `data`.
+pub fn inertia(data: &Array2<f32>, labels: &[usize]) -> f32 {
+    assert_eq!(data.nrows(), labels.len(), "each row must have a label");
+    let clusters = labels.iter().max().map_or(0, |m| m + 1);
+    let mut sums = Array2::<f32>::zeros((clusters, data.ncols()));
+    let mut counts = vec![0; clusters];
+    for (row, &l) in data.axis_iter(Axis(0)).zip(labels) {
+        let mut sum = sums.row_mut(l);
+        sum += &row;
+        counts[l] += 1;
+    }
+    for (mut sum, &count) in sums.axis_iter_mut(Axis(0)).zip(&counts) {
+        if count > 0 {
+            sum /= count as f32;
+        }
+    }
+    data.axis_iter(Axis(0))
+        .zip(labels)
+        .map(|(row, &l)| row.sq_l2_dist(&sums.row(l)).unwrap())
+        .sum()
+}
+
+/// Computes the Calinski-Harabasz index of a clustering.
+///
+/// This is the ratio of the dispersion between clusters to the dispersion within clusters, each
+/// scaled by its degrees of freedom. Higher values indicate denser, better separated clusters. The
+/// result is 0.0 if there are fewer than two clusters or no more rows than clusters, and infinite
+/// if every row lies exactly on its centroid.
+///
+/// # Panics
+///
+/// Panics if `labels` does not have a label for each row of `data`, or a label has no centroid.
+pub fn calinski_harabasz(data: &Array2<f32>, labels: &[usize], centroids: &[Array1<f32>]) -> f32 {
+    assert_eq!(data.nrows(), labels.len(), "each row must have a label");
+    let n = data.nrows();
+    let k = centroids.len();
+    if k < 2 || n <= k {
+        return 0.0;
+    }
+    let mean = data.mean_axis(Axis(0)).unwrap();
+    let mut counts = vec![0; k];
+    let mut within = 0.0;
+    for (row, &l) in data.axis_iter(Axis(0)).zip(labels) {
+        counts[l] += 1;
+        within += row.sq_l2_dist(&centroids[l]).unwrap();
+    }
+    let between: f32 = centroids
+        .iter()
+        .zip(&counts)
+        .map(|(c, &count)| count as f32 * c.sq_l2_dist(&mean).unwrap())
+        .sum();
+    if within == 0.0 {
+        return f32::INFINITY;
+    }
+    (between / (k - 1) as f32) / (within / (n - k) as f32)
+}
+
+/// Counts how often each true label occurs with each predicted label.
+///
+/// Element `(t, p)` of the result is the number of points with true label `t` and predicted label
+/// `p`. The result has a row for each label up to the largest true label, and a column for each
+/// label up to the largest predicted label.
+///
+/// # Panics
+///
+/// Panics if the labelings have different lengths.
+pub fn confusion_matrix(pred: &[usize], truth: &[usize]) -> Array2<usize> {
+    assert_eq!(
+        pred.len(),
+        truth.len(),
+        "labelings must have the same length"
+    );
+    let rows = truth.iter().max().map_or(0, |m| m + 1);
+    let cols = pred.iter().max().map_or(0, |m| m + 1);
+    let mut res = Array2::zeros((rows, cols));
+    for (&p, &t) in pred.iter().zip(truth) {
+        res[(t, p)] += 1;
+    }
+    res
+}
+
+/// Computes the purity of a clustering with respect to the true labels.
+///
+/// Each cluster is credited with the number of its points in its most common true class, and the
+/// result is the fraction of all points credited. It is 1.0 when no cluster mixes classes, and 0.0
+/// for no points.
+///
+/// # Panics
+///
+/// Panics if the labelings have different lengths.
+pub fn purity(pred: &[usize], truth: &[usize]) -> f32 {
+    if pred.is_empty() {
+        assert!(truth.is_empty(), "labelings must have the same length");
+        return 0.0;
+    }
+    let confusion = confusion_matrix(pred, truth);
+    let correct: usize = confusion
+        .axis_iter(Axis(1))
+        .map(|cluster| cluster.iter().copied().max().unwrap_or(0))
+        .sum();
+    correct as f32 / pred.len() as f32
+}
+
+/// Maps predicted cluster labels onto the ground truth labels they overlap with most.
+///
+/// Pairs of predicted and true clusters are matched greedily, taking the pair with the largest
+/// overlap among the unmatched clusters at each step. The returned vector contains the matched
+/// true label for each prediction.
+///
+/// # Panics
+///
+/// Panics if `truth` is empty or `pred` contains a label larger than the largest true label.
+pub fn align_labels(pred: &[usize], truth: &[usize]) -> Vec<usize> {
+    let num_clusters = truth.iter().max().unwrap() + 1;
+    let mut pred_map = vec![0; num_clusters];
+
+    let mut remaining_p: Vec<_> = (0..num_clusters).collect();
+    let mut remaining_t: Vec<_> = (0..num_clusters).collect();
+    while !remaining_p.is_empty() {
+        let (p, tr_match, _): (usize, usize, usize) = remaining_p
+            .iter()
+            .map(|p| {
+                let mut counts = HashMap::new();
+                pred.iter()
+                    .zip(truth)
+                    .filter(|(pr, _)| *pr == p)
+                    .filter(|(_, tr)| remaining_t.contains(tr))
+                    .for_each(|(_, tr)| {
+                        *counts.entry(tr).or_insert(0) += 1;
+                    });
+                let (tr, count): (usize, usize) = counts
+                    .iter()
+                    .map(|(&&tr, &c)| (tr, c))
+                    .max_by_key(|&(_, count)| count)
+                    .unwrap_or_else(|| (remaining_t[0], 0));
+                (*p, tr, count)
+            })
+            .max_by_key(|&(_, _, count)| count)
+            .unwrap();
+
+        pred_map[p] = tr_match;
+        remaining_p.retain(|&x| x != p);
+        remaining_t.retain(|&x| x != tr_match);
+    }
+    pred.iter().map(|p| pred_map[*p]).collect()
+}
+
+/// Maps predicted cluster labels onto ground truth labels, maximizing the total overlap.
+///
+/// Unlike `align_labels`, the matching of predicted and true clusters is optimal, found with the
+/// Hungarian algorithm. If there are more predicted clusters than true clusters, the unmatched
+/// predicted clusters are given distinct labels larger than the largest true label.
+pub fn align_labels_optimal(pred: &[usize], truth: &[usize]) -> Vec<usize> {
+    let overlap = confusion_matrix(pred, truth);
+    let n = std::cmp::max(overlap.nrows(), overlap.ncols());
+    let max = overlap.iter().cloned().max().unwrap_or(0) as i64;
+    // Maximizing the overlap is minimizing its difference from the maximum. Missing rows and
+    // columns pad the matrix to be square.
+    let cost = Array2::from_shape_fn((n, n), |(p, t)| {
+        max - overlap.get((t, p)).map_or(0, |&c| c as i64)
+    });
+    let pred_map = hungarian(&cost);
+    pred.iter().map(|&p| pred_map[p]).collect()
+}
+
+/// Solves the assignment problem for a square cost matrix with the Hungarian algorithm.
+///
+/// Returns the column assigned to each row, minimizing the total cost.
+fn hungarian(cost: &Array2<i64>) -> Vec<usize> {
+    let n = cost.nrows();
+    // Potentials and matching use 1-based indices, with index 0 as a sentinel.
+    let mut u = vec![0; n + 1];
+    let mut v = vec![0; n + 1];
+    let mut row_of = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut j0 = 0;
+        let mut min_v = vec![i64::MAX; n + 1];
+        let mut used = vec![false; n + 1];
+        // Grow an alternating path from row `i` until it reaches an unmatched column.
+        loop {
+            used[j0] = true;
+            let i0 = row_of[j0];
+            let mut delta = i64::MAX;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if !used[j] {
+                    let cur = cost[(i0 - 1, j - 1)] - u[i0] - v[j];
+                    if cur < min_v[j] {
+                        min_v[j] = cur;
+                        way[j] = j0;
+                    }
+                    if min_v[j] < delta {
+                        delta = min_v[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_v[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if row_of[j0] == 0 {
+                break;
+            }
+        }
+        // Flip the matching along the path.
+        while j0 != 0 {
+            let j1 = way[j0];
+            row_of[j0] = row_of[j1];
+            j0 = j1;
+        }
+    }
+    let mut res = vec![0; n];
+    for j in 1..=n {
+        res[row_of[j] - 1] = j - 1;
+    }
+    res
+}
+
+/// Estimates how stable a clustering into `k` clusters is under resampling of the data.
+///
+/// Each of the `runs` rounds draws two random subsamples of the rows without replacement, clusters
+/// both with `KMeans`, and computes the adjusted Rand index of the two labelings over the rows
+/// the subsamples share. The result is the mean over all rounds; values near 1.0 indicate that
+/// the data supports `k` clusters, while lower values indicate that the clustering mostly
+/// reflects the random initialization.
+pub fn bootstrap_stability(data: &Array2<f32>, k: usize, runs: usize, rng: &mut impl Rng) -> f32 {
+    let n = data.nrows();
+    let m = ((n as f32) * SUBSAMPLE_FRACTION).ceil() as usize;
+    if runs == 0 || m == 0 {
+        return 0.0;
+    }
+    let mut total = 0.0;
+    for _ in 0..runs {
+        let first = rand::seq::index::sample(rng, n, m).into_vec();
+        let second = rand::seq::index::sample(rng, n, m).into_vec();
+        let first_labels = KMeans::cluster(&data.select(Axis(0), &first), k, rng);
+        let second_labels = KMeans::cluster(&data.select(Axis(0), &second), k, rng);
+        // Position of each row within the first subsample.
+        let mut position = vec![None; n];
+        for (i, &row) in first.iter().enumerate() {
+            position[row] = Some(i);
+        }
+        let (a, b): (Vec<usize>, Vec<usize>) = second
+            .iter()
+            .zip(&second_labels)
+            .filter_map(|(&row, &l)| position[row].map(|i| (first_labels[i], l)))
+            .unzip();
+        total += adjusted_rand_index(&a, &b);
+    }
+    total / runs as f32
+}
+
+/// Estimates the purity of a clustering into `k` clusters on documents not used to find it.
+///
+/// A random `holdout_frac` of the rows is held out, and the rest are clustered with `KMeans`. Each
+/// held-out row is then assigned to its nearest centroid, and the purity of these assignments with
+/// respect to `truth` is returned. This is less optimistic than the purity on the clustered rows.
+/// The result is 0.0 if either the held-out or the clustered rows would be empty.
+///
+/// # Panics
+///
+/// Panics if `truth` does not have a label for each row of `data`.
+pub fn holdout_purity(
+    data: &Array2<f32>,
+    truth: &[usize],
+    k: usize,
+    holdout_frac: f32,
+    rng: &mut impl Rng,
+) -> f32 {
+    let n = data.nrows();
+    assert_eq!(truth.len(), n, "truth must have a label for each row");
+    let held = ((n as f32) * holdout_frac).round() as usize;
+    if held == 0 || held >= n {
+        return 0.0;
+    }
+    let mut is_held = vec![false; n];
+    for i in rand::seq::index::sample(rng, n, held).into_iter() {
+        is_held[i] = true;
+    }
+    let (holdout, train): (Vec<usize>, Vec<usize>) = (0..n).partition(|&i| is_held[i]);
+    let config = KMeansConfig::new(k);
+    let centroids = KMeans::fit(&data.select(Axis(0), &train), &config, rng).centroids;
+    if centroids.is_empty() {
+        return 0.0;
+    }
+    let pred = KMeans::predict(&data.select(Axis(0), &holdout), &centroids, config.metric);
+    let truth: Vec<usize> = holdout.iter().map(|&i| truth[i]).collect();
+    purity(&pred, &truth)
+}
+
+/// Computes the modularity of a clustering of the verticies of a graph.
+///
+/// This is the weighted modularity `Q`: the fraction of the total edge weight within clusters,
+/// minus the fraction expected if edges were placed at random while keeping the weighted degree
+/// of each vertex. It is at most 1.0, is 0.0 when all verticies are in one cluster, and is higher
+/// for partitions into densely connected clusters. Self-loops count twice towards the degree of
+/// their vertex. `labels` gives the cluster of each vertex, in the order of the verticies.
+///
+/// Returns NaN if the graph has no edge weight.
+///
+/// # Panics
+///
+/// Panics if there is not one label for each vertex.
+pub fn modularity<T: Value>(graph: &Graph<T>, labels: &[usize]) -> f32 {
+    assert_eq!(labels.len(), graph.len(), "expected one label per vertex");
+    let total = f64::from(graph.total_weight());
+    if total == 0.0 {
+        return f32::NAN;
+    }
+    let clusters = labels.iter().max().map_or(0, |&m| m + 1);
+    let mut internal = vec![0.0; clusters];
+    let mut degree = vec![0.0; clusters];
+    graph.fold_edges((), |(), (row, col, e)| {
+        let w = f64::from(e.value());
+        degree[labels[row]] += w;
+        degree[labels[col]] += w;
+        if labels[row] == labels[col] {
+            internal[labels[row]] += w;
+        }
+    });
+    internal
+        .iter()
+        .zip(&degree)
+        .map(|(l, d)| l / total - (d / (2.0 * total)).powi(2))
+        .sum::<f64>() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::IndexMap;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    /// Generates `per_blob` points around each of the given centers.
+    fn blobs(
+        centers: &[[f32; 2]],
+        per_blob: usize,
+        spread: f32,
+        rng: &mut impl Rng,
+    ) -> Array2<f32> {
+        let mut data = Array2::zeros((centers.len() * per_blob, 2));
+        for (i, mut row) in data.axis_iter_mut(Axis(0)).enumerate() {
+            let c = centers[i / per_blob];
+            row[0] = c[0] + rng.gen_range(-spread, spread);
+            row[1] = c[1] + rng.gen_range(-spread, spread);
+        }
+        data
+    }
+
+    #[test]
+    fn ari_identical_up_to_renaming() {
+        let a = [0, 0, 1, 1, 2, 2];
+        let b = [2, 2, 0, 0, 1, 1];
+        assert!((adjusted_rand_index(&a, &b) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ari_disagreement() {
+        let a = [0, 0, 0, 1, 1, 1];
+        let b = [0, 1, 2, 0, 1, 2];
+        assert!(adjusted_rand_index(&a, &b) < 0.0);
+    }
+
+    #[test]
+    fn purity_of_mixed_clusters() {
+        assert_eq!(purity(&[0, 0, 0, 1, 1, 1], &[0, 0, 1, 1, 1, 1]), 5.0 / 6.0);
+        assert_eq!(purity(&[0, 1, 2], &[1, 1, 1]), 1.0);
+        assert_eq!(purity(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn holdout_purity_separable() {
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let centers = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]];
+        let data = blobs(&centers, 20, 0.5, rng);
+        let truth: Vec<usize> = (0..data.nrows()).map(|i| i / 20).collect();
+        let p = holdout_purity(&data, &truth, 3, 0.25, rng);
+        assert!((0.0..=1.0).contains(&p));
+        assert!(p > 0.9);
+        assert_eq!(holdout_purity(&data, &truth, 3, 0.0, rng), 0.0);
+    }
+
+    #[test]
+    fn confusion() {
+        let pred = [0, 0, 1, 1, 2, 2, 0];
+        let truth = [1, 1, 0, 0, 0, 1, 1];
+        let expected = array![[0, 2, 1], [3, 0, 1]];
+        assert_eq!(confusion_matrix(&pred, &truth), expected);
+        assert_eq!(confusion_matrix(&[], &[]).dim(), (0, 0));
+    }
+
+    #[test]
+    fn optimal_alignment_beats_greedy() {
+        // Predicted cluster 0 overlaps most with true cluster 0, but matching it to true cluster 1
+        // lets predicted cluster 1 match true cluster 0.
+        let pairs = [(0, 0, 5), (0, 1, 4), (1, 0, 4), (2, 2, 3)];
+        let (pred, truth): (Vec<usize>, Vec<usize>) =
+            pairs.iter().flat_map(|&(p, t, n)| vec![(p, t); n]).unzip();
+        let correct =
+            |aligned: &[usize]| aligned.iter().zip(&truth).filter(|(a, t)| a == t).count();
+        let greedy = align_labels(&pred, &truth);
+        let optimal = align_labels_optimal(&pred, &truth);
+        assert_eq!(correct(&greedy), 8);
+        assert_eq!(correct(&optimal), 11);
+        assert_eq!(optimal[..10], [1, 1, 1, 1, 1, 1, 1, 1, 1, 0][..]);
+    }
+
+    #[test]
+    fn optimal_alignment_extra_clusters() {
+        let pred = [0, 1, 2, 3];
+        let truth = [1, 0, 0, 1];
+        let mut aligned = align_labels_optimal(&pred, &truth);
+        assert_eq!(aligned[..2], [1, 0]);
+        aligned.sort_unstable();
+        assert_eq!(aligned, [0, 1, 2, 3]);
+    }
+
+    /// Returns the mean of the rows with each label.
+    fn centroids(data: &Array2<f32>, labels: &[usize], k: usize) -> Vec<Array1<f32>> {
+        (0..k)
+            .map(|c| {
+                let rows: Vec<usize> = (0..labels.len()).filter(|&i| labels[i] == c).collect();
+                data.select(Axis(0), &rows).mean_axis(Axis(0)).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn calinski_harabasz_separation() {
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let labels: Vec<usize> = (0..60).map(|i| i / 20).collect();
+        let separated = blobs(&[[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]], 20, 1.0, rng);
+        let overlapping = blobs(&[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]], 20, 1.0, rng);
+        let separated_score =
+            calinski_harabasz(&separated, &labels, &centroids(&separated, &labels, 3));
+        let overlapping_score =
+            calinski_harabasz(&overlapping, &labels, &centroids(&overlapping, &labels, 3));
+        assert!(separated_score > 10.0 * overlapping_score);
+    }
+
+    #[test]
+    fn stability_blobs_vs_noise() {
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let clean = blobs(&[[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]], 30, 1.0, rng);
+        let noise = blobs(&[[0.0, 0.0]], 90, 10.0, rng);
+        let clean_stability = bootstrap_stability(&clean, 3, 10, rng);
+        let noise_stability = bootstrap_stability(&noise, 3, 10, rng);
+        assert!(clean_stability > 0.9);
+        assert!(noise_stability < clean_stability);
+    }
+
+    #[test]
+    fn modularity_two_blocks() {
+        let edges = [
+            ("a1", "a2", 1.0),
+            ("a1", "a3", 1.0),
+            ("a2", "a3", 1.0),
+            ("b1", "b2", 1.0),
+            ("b1", "b3", 1.0),
+            ("b2", "b3", 1.0),
+            ("a3", "b1", 1.0),
+        ];
+        let map: IndexMap = edges.iter().flat_map(|&(a, b, _)| vec![a, b]).collect();
+        let mut graph = Graph::new(map);
+        for &(a, b, w) in &edges {
+            *graph.get_mut(a, b).unwrap() = Some(w);
+        }
+        // Each block has 3 of the 7 edges and half of the total degree.
+        let q = modularity(&graph, &[0, 0, 0, 1, 1, 1]);
+        assert!((q - 2.0 * (3.0 / 7.0 - 0.25)).abs() < 1e-6);
+        assert!(modularity(&graph, &[0; 6]).abs() < 1e-6);
+        assert!(modularity(&graph, &[0, 1, 0, 1, 0, 1]) < 0.0);
+        assert!(modularity(&Graph::<f32>::new(IndexMap::new()), &[]).is_nan());
+    }
+}
@@ -0,0 +1,263 @@
+//! Sparse feature matrices and dimensionality reduction that does not densify them.
+
+use ndarray::prelude::*;
+use ndarray_linalg::{Eigh, UPLO};
+use rand::Rng;
+use rayon::prelude::*;
+
+/// Number of extra random directions sampled beyond the requested components.
+const OVERSAMPLING: usize = 10;
+/// Number of power iterations used to sharpen the sampled subspace.
+const POWER_ITERATIONS: usize = 4;
+
+/// A feature matrix in compressed sparse row format.
+///
+/// The entries of row `i` are stored in `indices[indptr[i]..indptr[i + 1]]` and the matching
+/// range of `data`, with column indices in increasing order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsrMatrix {
+    ncols: usize,
+    indptr: Vec<usize>,
+    indices: Vec<usize>,
+    data: Vec<f32>,
+}
+
+impl CsrMatrix {
+    /// Builds a matrix from rows of `(column, value)` pairs.
+    ///
+    /// Pairs within a row may be given in any order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a column index is not less than `ncols`.
+    pub fn from_rows(ncols: usize, rows: Vec<Vec<(usize, f32)>>) -> Self {
+        let mut indptr = Vec::with_capacity(rows.len() + 1);
+        let mut indices = Vec::new();
+        let mut data = Vec::new();
+        indptr.push(0);
+        for mut row in rows {
+            row.sort_unstable_by_key(|&(j, _)| j);
+            for (j, v) in row {
+                assert!(
+                    j < ncols,
+                    "column {} out of bounds for {} columns",
+                    j,
+                    ncols
+                );
+                indices.push(j);
+                data.push(v);
+            }
+            indptr.push(indices.len());
+        }
+        CsrMatrix {
+            ncols,
+            indptr,
+            indices,
+            data,
+        }
+    }
+
+    /// Builds a matrix holding the nonzero entries of `dense`.
+    pub fn from_dense(dense: &Array2<f32>) -> Self {
+        let rows = dense
+            .axis_iter(Axis(0))
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .filter(|&(_, &v)| v != 0.0)
+                    .map(|(j, &v)| (j, v))
+                    .collect()
+            })
+            .collect();
+        CsrMatrix::from_rows(dense.ncols(), rows)
+    }
+
+    /// Returns the number of rows.
+    pub fn nrows(&self) -> usize {
+        self.indptr.len() - 1
+    }
+
+    /// Returns the number of columns.
+    pub fn ncols(&self) -> usize {
+        self.ncols
+    }
+
+    /// Returns the number of stored entries.
+    pub fn nnz(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns an iterator over the `(column, value)` entries of row `i`.
+    pub fn row(&self, i: usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let range = self.indptr[i]..self.indptr[i + 1];
+        self.indices[range.clone()]
+            .iter()
+            .copied()
+            .zip(self.data[range].iter().copied())
+    }
+
+    /// Returns the mean of each column.
+    fn column_means(&self) -> Array1<f64> {
+        let mut sums = Array1::zeros(self.ncols);
+        for (&j, &v) in self.indices.iter().zip(&self.data) {
+            sums[j] += f64::from(v);
+        }
+        sums / self.nrows().max(1) as f64
+    }
+
+    /// Computes `self · m`, with the rows handled in parallel.
+    fn mul(&self, m: &Array2<f64>) -> Array2<f64> {
+        let rows: Vec<Array1<f64>> = (0..self.nrows())
+            .into_par_iter()
+            .map(|i| {
+                let mut acc = Array1::zeros(m.ncols());
+                for (j, v) in self.row(i) {
+                    acc.scaled_add(f64::from(v), &m.row(j));
+                }
+                acc
+            })
+            .collect();
+        let mut res = Array2::zeros((self.nrows(), m.ncols()));
+        for (mut out, row) in res.axis_iter_mut(Axis(0)).zip(&rows) {
+            out.assign(row);
+        }
+        res
+    }
+
+    /// Computes `selfᵀ · m`.
+    fn t_mul(&self, m: &Array2<f64>) -> Array2<f64> {
+        let mut res = Array2::zeros((self.ncols, m.ncols()));
+        for i in 0..self.nrows() {
+            for (j, v) in self.row(i) {
+                res.row_mut(j).scaled_add(f64::from(v), &m.row(i));
+            }
+        }
+        res
+    }
+}
+
+/// Reduces a sparse feature matrix to its top `k` principal components.
+///
+/// Gives the same result as PCA on the dense matrix, up to the sign of each component, without
+/// forming the dense matrix or its covariance. The columns are mean-centered implicitly, and the
+/// components are found with a randomized truncated SVD, so only products of the sparse matrix
+/// with thin dense matrices are computed. The result is exact when `k` plus a small oversampling
+/// is at least the rank of the centered matrix, and a close approximation otherwise.
+///
+/// The return type is of the format (rows × components), with components ordered by decreasing
+/// variance. Fewer than `k` components are returned if the matrix has fewer rows or columns.
+///
+/// Build the matrix from graphs with `clustering::vectorize_sparse`.
+///
+/// # Panics
+///
+/// Panics if `data` contains NaN or infinite values.
+pub fn reduce_dims_sparse<R: Rng>(data: &CsrMatrix, k: usize, rng: &mut R) -> Array2<f32> {
+    assert!(
+        data.data.iter().all(|v| v.is_finite()),
+        "non-finite value in feature matrix"
+    );
+    let (n, m) = (data.nrows(), data.ncols());
+    let l = (k + OVERSAMPLING).min(n).min(m);
+    let k = k.min(l);
+    let mean = data.column_means();
+    let mean_row = mean.view().insert_axis(Axis(0));
+
+    // Products with the centered matrix X - 1μᵀ.
+    let mul = |q: &Array2<f64>| data.mul(q) - &mean_row.dot(q);
+    let t_mul = |q: &Array2<f64>| {
+        let sums = q.sum_axis(Axis(0)).insert_axis(Axis(0));
+        data.t_mul(q) - &mean.view().insert_axis(Axis(1)).dot(&sums)
+    };
+
+    let omega = Array2::from_shape_fn((m, l), |_| rng.gen_range(-1.0, 1.0));
+    let mut q = orthonormalize(mul(&omega));
+    for _ in 0..POWER_ITERATIONS {
+        let z = orthonormalize(t_mul(&q));
+        q = orthonormalize(mul(&z));
+    }
+
+    // With B = Qᵀ(X - 1μᵀ) and BBᵀ = WΛWᵀ, the scores are QW√Λ.
+    let bt = t_mul(&q);
+    // The eigenvalues are in ascending order.
+    let (values, vectors) = bt
+        .t()
+        .dot(&bt)
+        .eigh(UPLO::Lower)
+        .expect("eigendecomposition failed");
+    let order: Vec<usize> = (0..l).rev().take(k).collect();
+    let scale = Array1::from(
+        order
+            .iter()
+            .map(|&i| values[i].max(0.0).sqrt())
+            .collect::<Vec<_>>(),
+    );
+    (q.dot(&vectors.select(Axis(1), &order)) * &scale).mapv(|v| v as f32)
+}
+
+/// Orthonormalizes the columns of `a` with modified Gram-Schmidt.
+///
+/// Columns that are linearly dependent on earlier ones are set to zero.
+fn orthonormalize(mut a: Array2<f64>) -> Array2<f64> {
+    for j in 0..a.ncols() {
+        for i in 0..j {
+            let proj = a.column(i).dot(&a.column(j));
+            let prev = a.column(i).to_owned();
+            a.column_mut(j).scaled_add(-proj, &prev);
+        }
+        let norm = a.column(j).dot(&a.column(j)).sqrt();
+        if norm > 1e-10 {
+            a.column_mut(j).mapv_inplace(|v| v / norm);
+        } else {
+            a.column_mut(j).fill(0.0);
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    #[test]
+    fn from_dense() {
+        let dense = array![[0.0, 1.0, 0.0], [2.0, 0.0, 3.0]];
+        let sparse = CsrMatrix::from_dense(&dense);
+        assert_eq!((sparse.nrows(), sparse.ncols(), sparse.nnz()), (2, 3, 3));
+        assert_eq!(sparse.row(1).collect::<Vec<_>>(), [(0, 2.0), (2, 3.0)]);
+        assert_eq!(
+            sparse,
+            CsrMatrix::from_rows(3, vec![vec![(1, 1.0)], vec![(2, 3.0), (0, 2.0)]])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "non-finite value")]
+    fn non_finite_value_panics() {
+        let data = CsrMatrix::from_rows(2, vec![vec![(0, 1.0)], vec![(1, f32::NAN)]]);
+        reduce_dims_sparse(&data, 1, &mut Pcg64Mcg::seed_from_u64(0));
+    }
+
+    #[test]
+    fn matches_dense_pca() {
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let dense = Array2::from_shape_fn((12, 6), |_| {
+            if rng.gen_range(0.0, 1.0) < 0.4 {
+                rng.gen_range(0.0, 5.0)
+            } else {
+                0.0
+            }
+        });
+        let expected = petal_decomposition::Pca::new(3)
+            .fit_transform(&dense)
+            .unwrap();
+        let reduced = reduce_dims_sparse(&CsrMatrix::from_dense(&dense), 3, rng);
+        assert_eq!(reduced.dim(), (12, 3));
+        for (a, b) in reduced.gencolumns().into_iter().zip(expected.gencolumns()) {
+            let sign = a.dot(&b).signum();
+            assert!(a.iter().zip(b).all(|(x, y)| (x - sign * y).abs() < 1e-3));
+        }
+    }
+}
@@ -16,6 +16,42 @@ pub const SIGMA_THRESHOLD: f32 = 0.4;
 /// Minimum CV^-1 to keep feature
 pub const CV_INV_THRESHOLD: f32 = 0.2;
 
+/// Parameters for the clustering pipeline.
+///
+/// The default value uses the constants in this module.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Number of clusters to produce
+    pub clusters: usize,
+    /// Number of dimensions to keep with PCA
+    pub pca_dims: usize,
+    /// Minimum standard deviation to keep feature
+    pub sigma_threshold: f32,
+    /// Minimum CV^-1 to keep feature
+    pub cv_inv_threshold: f32,
+    /// Number of threads used by the parallel parts of the pipeline. If `None`, the global
+    /// thread pool is used.
+    pub threads: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            clusters: CLUSTERS,
+            pca_dims: PCA_DIMS,
+            sigma_threshold: SIGMA_THRESHOLD,
+            cv_inv_threshold: CV_INV_THRESHOLD,
+            threads: None,
+        }
+    }
+}
+
+/// Whether graphs are stored with `graph::io::write_binary` rather than as JSON
+pub const BINARY_GRAPHS: bool = false;
+
+/// Metadata key holding the true class of each document
+pub const CLASS_KEY: &str = "class";
+
 /// Type used for graph edges
 pub type EdgeType = f32;
 /// Graph construction method
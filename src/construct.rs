@@ -19,7 +19,10 @@ where
     ///
     /// Panics if the graph does not contain the verticies.
     fn add_weight(&mut self, t1: &str, t2: &str, weight: T) {
-        let edge = self.get_mut(&t1, &t2).unwrap();
+        let edge = match self.get_mut_checked(t1, t2) {
+            Ok(edge) => edge,
+            Err(e) => panic!("{}", e),
+        };
         *edge = match *edge {
             Some(v) => Some(v + weight),
             None => Some(weight),
@@ -113,7 +116,7 @@ pub fn construct_sentence_link(document: &Document) -> Graph<()> {
             let mut sent_iter = sentence.iter();
             while let Some(term) = sent_iter.next() {
                 for t in sent_iter.clone() {
-                    *graph.get_mut(&term, &t).unwrap() = Some(());
+                    *graph.get_mut_checked(term, t).unwrap() = Some(());
                 }
             }
         }
@@ -4,9 +4,12 @@
 
 mod adj_matrix;
 mod index_trie;
+pub mod io;
 pub mod lower_triangular;
 
 #[cfg(feature = "adj_matrix")]
 pub use adj_matrix::AMGraph as Graph;
+#[cfg(feature = "adj_matrix")]
+pub use adj_matrix::{EdgeDiff, MissingVertex};
 
 pub use index_trie::IndexTrie as IndexMap;
@@ -1,7 +1,18 @@
 //! Adjacency matrix based graph implementation.
 
-use crate::graph::{IndexMap,lower_triangular::LowerTriangular};
+use crate::{
+    clustering::Value,
+    graph::{lower_triangular::LowerTriangular, IndexMap},
+};
+use ndarray::prelude::*;
+use rand::Rng;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashSet, VecDeque},
+    error::Error,
+    fmt,
+};
 
 /// Graph implementation based off of an adjacency matrix graph implementation.
 /// Represents edges as a lower triangular matrix encoded as a jagged array, allowing for adding
@@ -24,6 +35,14 @@ impl<E> AMGraph<E> {
         }
     }
 
+    /// Creates an empty graph over the same verticies, with a possibly different edge type.
+    ///
+    /// Only the vertex map is cloned, so several graphs can be built over one vocabulary without
+    /// rebuilding it.
+    pub fn clone_structure_with<U>(&self) -> AMGraph<U> {
+        AMGraph::new(self.map.clone())
+    }
+
     /// Returns the number of verticies in the graph.
     pub fn len(&self) -> usize {
         self.map.len()
@@ -34,6 +53,14 @@ impl<E> AMGraph<E> {
         self.map.into_iter()
     }
 
+    /// Returns the index of `v` in the order of `vertices`, or `None` if the graph does not
+    /// contain `v`.
+    ///
+    /// This allows indexing arrays with an element per vertex, such as cluster labels.
+    pub fn vertex_index(&self, v: &str) -> Option<usize> {
+        self.map.get(v)
+    }
+
     /// Returns an iterator over the edges of the graph.
     ///
     /// The return type is of the format (row, column, edge).
@@ -45,6 +72,57 @@ impl<E> AMGraph<E> {
         }
     }
 
+    /// Folds over the edges of the graph in a single pass over the edge storage.
+    ///
+    /// Edges are visited in the same order as `edges`, but by vertex index rather than label, so
+    /// no `String`s are allocated. The edge is passed to `f` in the format (row, column, edge),
+    /// where row >= column.
+    pub fn fold_edges<'a, B, F>(&'a self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, (usize, usize, &'a E)) -> B,
+    {
+        let mut acc = init;
+        let mut slots = self.edges.iter();
+        for row in 0..self.len() {
+            for col in 0..=row {
+                if let Some(Some(e)) = slots.next() {
+                    acc = f(acc, (row, col, e));
+                }
+            }
+        }
+        acc
+    }
+
+    /// Returns a uniform random sample of `k` edges, or all edges if there are at most `k`.
+    ///
+    /// Uses reservoir sampling over `edges`, so only the sample is kept in memory. The order of the
+    /// sampled edges is unspecified.
+    pub fn sample_edges<R: Rng>(&self, k: usize, rng: &mut R) -> Vec<(String, String, &E)> {
+        let n = self.len();
+        let mut res = Vec::with_capacity(k.min(n * (n + 1) / 2));
+        for (i, edge) in self.edges().enumerate() {
+            if i < k {
+                res.push(edge);
+            } else {
+                let j = rng.gen_range(0, i + 1);
+                if j < k {
+                    res[j] = edge;
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns an iterator over the verticies that have an edge to themselves.
+    ///
+    /// The return type is of the format (vertex, edge).
+    pub fn self_loops(&self) -> impl Iterator<Item = (String, &E)> {
+        self.map
+            .into_iter()
+            .enumerate()
+            .filter_map(move |(i, v)| self.edges[(i, i)].as_ref().map(|e| (v, e)))
+    }
+
     /// Returns `true` if the graph contains no verticies.
     pub fn is_empty(&self) -> bool {
         self.map.is_empty()
@@ -60,6 +138,45 @@ impl<E> AMGraph<E> {
         Ok(&self.edges[(v1, v2)])
     }
 
+    /// Returns a reference to the edge between the given verticies.
+    ///
+    /// Unlike `get`, the error names the verticies that are not in the graph.
+    pub fn get_checked(&self, v1: &str, v2: &str) -> Result<&Option<E>, MissingVertex> {
+        let (i1, i2) = self.checked_indices(v1, v2)?;
+        Ok(&self.edges[(i1, i2)])
+    }
+
+    /// Returns a mutable reference to the edge between the given verticies.
+    ///
+    /// Unlike `get_mut`, the error names the verticies that are not in the graph.
+    pub fn get_mut_checked(&mut self, v1: &str, v2: &str) -> Result<&mut Option<E>, MissingVertex> {
+        let (i1, i2) = self.checked_indices(v1, v2)?;
+        Ok(&mut self.edges[(i1, i2)])
+    }
+
+    fn checked_indices(&self, v1: &str, v2: &str) -> Result<(usize, usize), MissingVertex> {
+        match (self.map.get(v1), self.map.get(v2)) {
+            (Some(i1), Some(i2)) => Ok((i1, i2)),
+            (i1, i2) => {
+                let mut vertices = Vec::new();
+                if i1.is_none() {
+                    vertices.push(v1.to_string());
+                }
+                if i2.is_none() && (v1 != v2 || vertices.is_empty()) {
+                    vertices.push(v2.to_string());
+                }
+                Err(MissingVertex { vertices })
+            }
+        }
+    }
+
+    /// Returns `true` if there is an edge between the given verticies, in either order.
+    ///
+    /// Returns `false` if either vertex is not in the graph.
+    pub fn contains_edge(&self, v1: &str, v2: &str) -> bool {
+        matches!(self.get(v1, v2), Ok(Some(_)))
+    }
+
     /// Returns a mutable reference to the edge between the given verticies.
     ///
     /// Return value will be `Err` if the verticies are not in the graph, otherwise the value will
@@ -70,10 +187,641 @@ impl<E> AMGraph<E> {
         Ok(&mut self.edges[(v1, v2)])
     }
 
+    /// Removes the edge between the given verticies.
+    ///
+    /// Returns the removed edge, or `None` if either vertex is not in the graph or there was no
+    /// edge.
+    pub fn remove_edge(&mut self, v1: &str, v2: &str) -> Option<E> {
+        self.get_mut(v1, v2).ok()?.take()
+    }
+
+    /// Converts the graph into its verticies and edge matrix, for use with integer indices.
+    ///
+    /// The verticies are in sorted order, so the index of each vertex is its position in the
+    /// returned `Vec`. The matrix can be indexed by a pair of vertex indices in either order.
+    pub fn relabel_to_indices(self) -> (Vec<String>, LowerTriangular<Option<E>>) {
+        (self.map.into_iter().collect(), self.edges)
+    }
+
+    /// Returns `true` if the edge storage is consistent with an undirected graph.
+    ///
+    /// This holds if there is exactly one edge slot for each unordered pair of verticies. Looking
+    /// up an edge in either direction always resolves to the same slot of the lower triangular
+    /// storage, so only the number of slots needs to be checked.
+    pub fn is_symmetric(&self) -> bool {
+        let len = self.len();
+        self.edges.len() == (len * (len + 1)) / 2
+    }
+
+    /// Asserts that the edge storage is consistent with an undirected graph in debug builds.
+    ///
+    /// See `is_symmetric` for the conditions checked.
+    pub fn assert_symmetric(&self) {
+        debug_assert!(self.is_symmetric(), "graph storage is not symmetric");
+    }
+
     /// Returns `true` if the graph contains the given vertex.
     pub fn contains_vertex(&self, v: &str) -> bool {
         self.map.get(v).is_some()
     }
+
+    /// Returns an iterator over the verticies adjacent to `v`, excluding `v` itself.
+    ///
+    /// The return type is of the format (vertex, edge). Returns `None` if the graph does not
+    /// contain `v`.
+    pub fn neighbors(&self, v: &str) -> Option<impl Iterator<Item = (String, &E)>> {
+        let i = self.map.get(v)?;
+        Some(
+            self.map
+                .into_iter()
+                .enumerate()
+                .filter(move |&(j, _)| j != i)
+                .filter_map(move |(j, w)| self.edges[(i, j)].as_ref().map(|e| (w, e))),
+        )
+    }
+
+    /// Returns the Jaccard index of the neighbors of `a` and `b`, as given by `neighbors`.
+    ///
+    /// This is the number of verticies adjacent to both divided by the number adjacent to either,
+    /// or 0.0 if neither has neighbors. Returns `None` if the graph does not contain `a` or `b`.
+    pub fn jaccard_similarity(&self, a: &str, b: &str) -> Option<f32> {
+        let (i, j) = (self.map.get(a)?, self.map.get(b)?);
+        let (mut both, mut either) = (0, 0);
+        for k in 0..self.len() {
+            let in_a = k != i && self.edges[(i, k)].is_some();
+            let in_b = k != j && self.edges[(j, k)].is_some();
+            both += (in_a && in_b) as usize;
+            either += (in_a || in_b) as usize;
+        }
+        Some(if either == 0 {
+            0.0
+        } else {
+            both as f32 / either as f32
+        })
+    }
+
+    /// Returns the subgraph induced by the given verticies.
+    ///
+    /// The result contains the given verticies and the edges between them. Verticies not in the
+    /// graph are ignored.
+    pub fn subgraph(&self, vertices: &[&str]) -> AMGraph<E>
+    where
+        E: Clone,
+    {
+        let mut indices: Vec<usize> = vertices.iter().filter_map(|v| self.map.get(*v)).collect();
+        indices.sort_unstable();
+        indices.dedup();
+        self.induced(&indices)
+    }
+
+    /// Returns the subgraph induced by the verticies for which `keep` returns `true`.
+    pub fn filter_vertices<F: Fn(&str) -> bool>(&self, keep: F) -> AMGraph<E>
+    where
+        E: Clone,
+    {
+        let indices: Vec<usize> = self
+            .map
+            .into_iter()
+            .enumerate()
+            .filter(|(_, v)| keep(v))
+            .map(|(i, _)| i)
+            .collect();
+        self.induced(&indices)
+    }
+
+    /// Returns the subgraph induced by the verticies with the given indices, which must be sorted
+    /// and distinct.
+    fn induced(&self, indices: &[usize]) -> AMGraph<E>
+    where
+        E: Clone,
+    {
+        AMGraph::induced_with(&self.map, indices, |i, j| self.edges[(i, j)].clone())
+    }
+
+    /// Builds the subgraph of the graph with verticies `map` induced by the verticies with the
+    /// given indices, which must be sorted and distinct, getting each of its edges from `edge`.
+    fn induced_with<F>(map: &IndexMap, indices: &[usize], mut edge: F) -> AMGraph<E>
+    where
+        F: FnMut(usize, usize) -> Option<E>,
+    {
+        let map = IndexMap::from_sorted(
+            &indices
+                .iter()
+                .map(|&i| map.get(i).unwrap())
+                .collect::<Vec<_>>(),
+        );
+        let mut res = AMGraph::new(map);
+        for (row, &i) in indices.iter().enumerate() {
+            for (col, &j) in indices[..=row].iter().enumerate() {
+                res.edges[(row, col)] = edge(i, j);
+            }
+        }
+        res
+    }
+
+    /// Returns the subgraph induced by the verticies within `radius` edges of `v`.
+    ///
+    /// A radius of 0 gives just `v`, and a radius of 1 gives `v` and its neighbors. Returns `None`
+    /// if the graph does not contain `v`.
+    pub fn ego_network(&self, v: &str, radius: usize) -> Option<AMGraph<E>>
+    where
+        E: Clone,
+    {
+        if !self.contains_vertex(v) {
+            return None;
+        }
+        let mut reached = HashSet::new();
+        reached.insert(v.to_string());
+        let mut frontier = vec![v.to_string()];
+        for _ in 0..radius {
+            let mut next = Vec::new();
+            for u in &frontier {
+                for (w, _) in self.neighbors(u).unwrap() {
+                    if reached.insert(w.clone()) {
+                        next.push(w);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        let vertices: Vec<&str> = reached.iter().map(|w| w.as_str()).collect();
+        Some(self.subgraph(&vertices))
+    }
+
+    /// Returns the graph of the edges present in both graphs, with the weights from `self`.
+    ///
+    /// See `intersection_with` for details.
+    pub fn intersection(&self, other: &AMGraph<E>) -> AMGraph<E>
+    where
+        E: Copy,
+    {
+        self.intersection_with(other, |&e, _| e)
+    }
+
+    /// Returns the graph of the edges present in both graphs, combining their weights with `f`.
+    ///
+    /// The result contains the verticies found in both graphs, matched by label. `f` is given the
+    /// edge from `self` and the edge from `other`.
+    pub fn intersection_with<F: FnMut(&E, &E) -> E>(
+        &self,
+        other: &AMGraph<E>,
+        mut f: F,
+    ) -> AMGraph<E> {
+        // Pairs of indices in `self` and `other` for each shared vertex, in sorted order.
+        let shared: Vec<(usize, usize)> = self
+            .map
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, v)| other.map.get(&*v).map(|j| (i, j)))
+            .collect();
+        let map = IndexMap::from_sorted(
+            &shared
+                .iter()
+                .map(|&(i, _)| self.map.get(i).unwrap())
+                .collect::<Vec<_>>(),
+        );
+        let mut res = AMGraph::new(map);
+        for (row, &(i1, j1)) in shared.iter().enumerate() {
+            for (col, &(i2, j2)) in shared[..=row].iter().enumerate() {
+                if let (Some(e1), Some(e2)) = (&self.edges[(i1, i2)], &other.edges[(j1, j2)]) {
+                    res.edges[(row, col)] = Some(f(e1, e2));
+                }
+            }
+        }
+        res
+    }
+
+    /// Compares the edges of `self` against those of an earlier version of the graph, `other`.
+    ///
+    /// Edges are matched by the labels of their verticies, so the graphs may have different
+    /// verticies. Edges are listed in the same order as `edges`.
+    pub fn diff_edges(&self, other: &AMGraph<E>) -> EdgeDiff<E>
+    where
+        E: Clone + PartialEq,
+    {
+        let mut diff = EdgeDiff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        };
+        for (v1, v2, e) in self.edges() {
+            match other.get(&v1, &v2) {
+                Ok(Some(old)) => {
+                    if old != e {
+                        diff.changed.push((v1, v2, old.clone(), e.clone()));
+                    }
+                }
+                _ => diff.added.push((v1, v2, e.clone())),
+            }
+        }
+        for (v1, v2, e) in other.edges() {
+            if !self.contains_edge(&v1, &v2) {
+                diff.removed.push((v1, v2, e.clone()));
+            }
+        }
+        diff
+    }
+
+    /// Removes the verticies that have no edges to other verticies.
+    ///
+    /// Self-loops are ignored when determining whether a vertex is isolated, as construction
+    /// methods such as `construct_hierarchial_weighed` may give every vertex one.
+    ///
+    /// Returns the number of verticies removed.
+    pub fn prune_isolated(&mut self) -> usize {
+        let len = self.len();
+        let mut connected = vec![false; len];
+        for row in 0..len {
+            for col in 0..row {
+                if self.edges[(row, col)].is_some() {
+                    connected[row] = true;
+                    connected[col] = true;
+                }
+            }
+        }
+        let kept: Vec<usize> = (0..len).filter(|&i| connected[i]).collect();
+        if kept.len() < len {
+            // Edges are moved rather than cloned, as the old graph is discarded.
+            let edges = &mut self.edges;
+            *self = AMGraph::induced_with(&self.map, &kept, |i, j| edges[(i, j)].take());
+        }
+        len - kept.len()
+    }
+}
+
+impl<E: Value> AMGraph<E> {
+    /// Returns the verticies adjacent to `v`, excluding `v` itself, by descending edge value.
+    ///
+    /// Verticies with equal edge values are in sorted order. The return type is of the format
+    /// (vertex, edge value). Returns an empty `Vec` if the graph does not contain `v`.
+    pub fn neighbors_sorted(&self, v: &str) -> Vec<(String, f32)> {
+        let mut res: Vec<(String, f32)> = match self.neighbors(v) {
+            Some(n) => n.map(|(w, e)| (w, e.value())).collect(),
+            None => return Vec::new(),
+        };
+        res.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        res
+    }
+
+    /// Returns the edge with the largest value, or `None` if the graph has no edges.
+    ///
+    /// Self-loops are included, and ties are broken by the order of `edges`. The return type is of
+    /// the format (row, column, edge value).
+    pub fn max_edge(&self) -> Option<(String, String, f32)> {
+        self.extreme_edge(|a, b| a > b)
+    }
+
+    /// Returns the edge with the smallest value, or `None` if the graph has no edges.
+    ///
+    /// See `max_edge` for details.
+    pub fn min_edge(&self) -> Option<(String, String, f32)> {
+        self.extreme_edge(|a, b| a < b)
+    }
+
+    /// Returns the first edge whose value is not beaten by any other according to `better`.
+    fn extreme_edge<F: Fn(f32, f32) -> bool>(&self, better: F) -> Option<(String, String, f32)> {
+        self.fold_edges(None, |best, (row, col, e)| {
+            let value = e.value();
+            if value.is_nan() {
+                return best;
+            }
+            match best {
+                Some((_, _, b)) if !better(value, b) => best,
+                _ => Some((row, col, value)),
+            }
+        })
+        .map(|(row, col, value)| {
+            (
+                self.map.get(row).unwrap(),
+                self.map.get(col).unwrap(),
+                value,
+            )
+        })
+    }
+
+    /// Removes the edges with a value less than `min`, returning them.
+    ///
+    /// Self-loops are included. The removed edges are in the same order as `edges`, in the format
+    /// (row, column, edge value).
+    pub fn threshold_edges_logged(&mut self, min: f32) -> Vec<(String, String, f32)> {
+        let mut removed = Vec::new();
+        for row in 0..self.len() {
+            for col in 0..=row {
+                let slot = &mut self.edges[(row, col)];
+                if let Some(value) = slot.as_ref().map(Value::value) {
+                    if value < min {
+                        *slot = None;
+                        removed.push((
+                            self.map.get(row).unwrap(),
+                            self.map.get(col).unwrap(),
+                            value,
+                        ));
+                    }
+                }
+            }
+        }
+        removed
+    }
+
+    /// Returns the sum of the values of the edges of `v`, or `None` if the graph does not contain
+    /// `v`.
+    ///
+    /// A self-loop counts twice, once for each of its ends, as in
+    /// `clustering::metrics::modularity`.
+    pub fn weighted_degree(&self, v: &str) -> Option<f32> {
+        let i = self.map.get(v)?;
+        let edges: f32 = (0..self.len())
+            .filter_map(|j| self.edges[(i, j)].as_ref())
+            .map(Value::value)
+            .sum();
+        Some(edges + self.edges[(i, i)].as_ref().map_or(0.0, Value::value))
+    }
+
+    /// Returns the value of the self-loop of each vertex, or 0.0 for verticies without one.
+    ///
+    /// Values are in the order of the verticies, as given by `vertices`.
+    pub fn diagonal(&self) -> Array1<f32> {
+        (0..self.len())
+            .map(|i| self.edges[(i, i)].as_ref().map_or(0.0, Value::value))
+            .collect()
+    }
+
+    /// Returns an iterator over the edges of the graph with a value greater than `threshold`.
+    ///
+    /// The return type is of the format (row, column, edge).
+    pub fn edges_above(&self, threshold: f32) -> impl Iterator<Item = (String, String, &E)> {
+        self.edges().filter(move |(_, _, e)| e.value() > threshold)
+    }
+
+    /// Returns the weighted local clustering coefficient of `v`.
+    ///
+    /// This is the geometric mean of the normalized edge weights of each triangle through `v`,
+    /// averaged over all pairs of neighbors of `v`, with weights normalized by the largest edge
+    /// weight in the graph. It is 1.0 when every pair of neighbors is connected with maximal
+    /// weight. Self-loops are ignored.
+    ///
+    /// Returns `None` if the graph does not contain `v`, and 0.0 if `v` has fewer than two
+    /// neighbors.
+    pub fn clustering_coefficient(&self, v: &str) -> Option<f32> {
+        let i = self.map.get(v)?;
+        let neighbors: Vec<(usize, f32)> = (0..self.len())
+            .filter(|&j| j != i)
+            .filter_map(|j| self.edges[(i, j)].as_ref().map(|e| (j, e.value())))
+            .collect();
+        let k = neighbors.len();
+        let max = (0..self.len())
+            .flat_map(|row| (0..row).map(move |col| (row, col)))
+            .filter_map(|index| self.edges[index].as_ref())
+            .map(Value::value)
+            .fold(0.0, f32::max);
+        if k < 2 || max <= 0.0 {
+            return Some(0.0);
+        }
+        let mut sum = 0.0;
+        for (a, &(ja, wa)) in neighbors.iter().enumerate() {
+            for &(jb, wb) in &neighbors[a + 1..] {
+                if let Some(e) = &self.edges[(ja, jb)] {
+                    sum += (wa * wb * e.value() / (max * max * max)).cbrt();
+                }
+            }
+        }
+        Some(2.0 * sum / (k * (k - 1)) as f32)
+    }
+
+    /// Counts the edges of the graph, including self-loops, in `bins` equal-width bins spanning
+    /// the smallest to the largest edge value.
+    ///
+    /// If all edges have the same value, they are counted in the first bin.
+    pub fn weight_histogram(&self, bins: usize) -> Vec<usize> {
+        let mut counts = vec![0; bins];
+        if bins == 0 {
+            return counts;
+        }
+        let values: Vec<f32> = self.edges().map(|(_, _, e)| e.value()).collect();
+        let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        for v in values {
+            let bin = if max > min {
+                ((v - min) / (max - min) * bins as f32) as usize
+            } else {
+                0
+            };
+            counts[std::cmp::min(bin, bins - 1)] += 1;
+        }
+        counts
+    }
+
+    /// Returns the sum of the values of the edges of the graph.
+    ///
+    /// Each edge is counted once, including self-loops. Measures such as modularity that count
+    /// each edge from both ends should count self-loops twice.
+    pub fn total_weight(&self) -> f32 {
+        self.fold_edges(0.0, |acc, (_, _, e)| acc + e.value())
+    }
+
+    /// Returns the edges of the graph as owned (vertex, vertex, weight) triples.
+    ///
+    /// Edges are in the same order as `edges`, and self-loops are included.
+    pub fn to_edge_list(&self) -> Vec<(String, String, f32)> {
+        self.edges()
+            .map(|(v1, v2, e)| (v1, v2, e.value()))
+            .collect()
+    }
+
+    /// Computes the PageRank of each vertex by power iteration.
+    ///
+    /// A random walk moves from a vertex to a neighbor with probability proportional to the
+    /// weight of the edge between them, and with probability `1 - damping` jumps to a uniformly
+    /// random vertex instead. Self-loops are ignored, and the rank of verticies without other
+    /// edges is spread uniformly over all verticies. Iteration stops after `max_iter` iterations,
+    /// or once the ranks change by less than `tol` in total.
+    ///
+    /// The return type is of the format (vertex, rank), with the verticies in sorted order. The
+    /// ranks sum to 1.
+    pub fn pagerank(&self, damping: f32, max_iter: usize, tol: f32) -> Vec<(String, f32)> {
+        let n = self.len();
+        let mut neighbors = vec![Vec::new(); n];
+        let mut out_weight = vec![0.0; n];
+        self.fold_edges((), |(), (row, col, e)| {
+            let w = e.value();
+            if row != col && w > 0.0 {
+                neighbors[row].push((col, w));
+                neighbors[col].push((row, w));
+                out_weight[row] += w;
+                out_weight[col] += w;
+            }
+        });
+        let mut rank = vec![1.0 / n as f32; n];
+        for _ in 0..max_iter {
+            let dangling: f32 = (0..n)
+                .filter(|&u| out_weight[u] == 0.0)
+                .map(|u| rank[u])
+                .sum();
+            let base = (1.0 - damping) / n as f32 + damping * dangling / n as f32;
+            let mut next = vec![base; n];
+            for u in 0..n {
+                for &(v, w) in &neighbors[u] {
+                    next[v] += damping * rank[u] * w / out_weight[u];
+                }
+            }
+            let change: f32 = rank.iter().zip(&next).map(|(a, b)| (a - b).abs()).sum();
+            rank = next;
+            if change < tol {
+                break;
+            }
+        }
+        self.map.into_iter().zip(rank).collect()
+    }
+
+    /// Computes the betweenness centrality of each vertex with Brandes' algorithm.
+    ///
+    /// The betweenness of a vertex is the sum, over all unordered pairs of other verticies, of the
+    /// fraction of shortest paths between them that pass through it. Every edge with a positive
+    /// value has unit length, so edge values only decide whether verticies are adjacent;
+    /// self-loops are ignored. Shortest paths from each source are found in parallel.
+    ///
+    /// The return type is of the format (vertex, betweenness), with the verticies in sorted order.
+    pub fn betweenness_centrality(&self) -> Vec<(String, f32)> {
+        let n = self.len();
+        let mut neighbors = vec![Vec::new(); n];
+        self.fold_edges((), |(), (row, col, e)| {
+            if row != col && e.value() > 0.0 {
+                neighbors[row].push(col);
+                neighbors[col].push(row);
+            }
+        });
+        let centrality = (0..n)
+            .into_par_iter()
+            .fold(
+                || vec![0.0; n],
+                |mut acc, source| {
+                    // Breadth-first search, counting the shortest paths to each vertex.
+                    let mut order = Vec::with_capacity(n);
+                    let mut preds = vec![Vec::new(); n];
+                    let mut paths = vec![0.0f32; n];
+                    let mut dist = vec![usize::MAX; n];
+                    let mut queue = VecDeque::new();
+                    paths[source] = 1.0;
+                    dist[source] = 0;
+                    queue.push_back(source);
+                    while let Some(v) = queue.pop_front() {
+                        order.push(v);
+                        for &w in &neighbors[v] {
+                            if dist[w] == usize::MAX {
+                                dist[w] = dist[v] + 1;
+                                queue.push_back(w);
+                            }
+                            if dist[w] == dist[v] + 1 {
+                                paths[w] += paths[v];
+                                preds[w].push(v);
+                            }
+                        }
+                    }
+                    // Accumulate dependencies in order of decreasing distance from the source.
+                    let mut dependency = vec![0.0f32; n];
+                    for &w in order.iter().rev() {
+                        for &v in &preds[w] {
+                            dependency[v] += paths[v] / paths[w] * (1.0 + dependency[w]);
+                        }
+                        if w != source {
+                            acc[w] += dependency[w];
+                        }
+                    }
+                    acc
+                },
+            )
+            .reduce(
+                || vec![0.0; n],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(x, y)| *x += y);
+                    a
+                },
+            );
+        // Each unordered pair is counted once from each end.
+        self.map
+            .into_iter()
+            .zip(centrality.into_iter().map(|c| c / 2.0))
+            .collect()
+    }
+
+    /// Computes the degree assortativity of the graph.
+    ///
+    /// This is the Pearson correlation between the degrees of the verticies at either end of each
+    /// edge, with each edge counted in both directions. It is positive when verticies of high
+    /// degree tend to be adjacent to each other, and negative when they tend to be adjacent to
+    /// verticies of low degree. Degrees count the edges with a positive value, and self-loops are
+    /// ignored.
+    ///
+    /// Returns NaN if there are no edges, or if the verticies at the ends of the edges all have
+    /// the same degree, as the correlation is then undefined.
+    pub fn degree_assortativity(&self) -> f32 {
+        let mut degree = vec![0usize; self.len()];
+        let mut pairs = Vec::new();
+        self.fold_edges((), |(), (row, col, e)| {
+            if row != col && e.value() > 0.0 {
+                degree[row] += 1;
+                degree[col] += 1;
+                pairs.push((row, col));
+            }
+        });
+        if pairs.is_empty() {
+            return f32::NAN;
+        }
+        let n = 2.0 * pairs.len() as f64;
+        let (mut sum, mut sum_sq, mut sum_prod) = (0.0, 0.0, 0.0);
+        for (a, b) in pairs {
+            let (x, y) = (degree[a] as f64, degree[b] as f64);
+            sum += x + y;
+            sum_sq += x * x + y * y;
+            sum_prod += 2.0 * x * y;
+        }
+        // Both ends have the same degree distribution, as each edge is counted both ways.
+        let mean = sum / n;
+        let variance = sum_sq / n - mean * mean;
+        if variance <= 0.0 {
+            return f32::NAN;
+        }
+        ((sum_prod / n - mean * mean) / variance) as f32
+    }
+}
+
+impl AMGraph<f32> {
+    /// Multiplies the weight of every edge by `factor`. Absent edges stay absent.
+    pub fn scale_edges(&mut self, factor: f32) {
+        for e in self.edges.iter_mut().flatten() {
+            *e *= factor;
+        }
+    }
+}
+
+/// Error returned when looking up an edge whose verticies are not in the graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingVertex {
+    /// The verticies that are not in the graph.
+    pub vertices: Vec<String>,
+}
+
+impl fmt::Display for MissingVertex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "vertex not in graph: {}", self.vertices.join(", "))
+    }
+}
+
+impl Error for MissingVertex {}
+
+/// The differences between the edges of two graphs, as returned by `AMGraph::diff_edges`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EdgeDiff<E> {
+    /// Edges only in the newer graph, in the format (vertex, vertex, edge).
+    pub added: Vec<(String, String, E)>,
+    /// Edges only in the older graph, in the format (vertex, vertex, edge).
+    pub removed: Vec<(String, String, E)>,
+    /// Edges in both graphs with different weights, in the format (vertex, vertex, old edge, new
+    /// edge).
+    pub changed: Vec<(String, String, E, E)>,
 }
 
 /// An iterator over the edges of an `AMGraph`.
@@ -119,3 +867,499 @@ impl<'a, E> Iterator for Edges<'a, E> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        construct::{construct_hierarchial_weighed, construct_sentence_count},
+        input::{InputFormat, NddFile},
+    };
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+    use std::collections::HashMap;
+
+    /// Builds a graph with the given weighted edges.
+    fn weighted(edges: &[(&str, &str, f32)]) -> AMGraph<f32> {
+        let map: IndexMap = edges.iter().flat_map(|&(a, b, _)| vec![a, b]).collect();
+        let mut g = AMGraph::new(map);
+        for &(a, b, w) in edges {
+            *g.get_mut(a, b).unwrap() = Some(w);
+        }
+        g
+    }
+
+    fn test_graph() -> AMGraph<f32> {
+        let document = NddFile::parse("cat dog\ndog bird\n\nbird fish".as_bytes()).unwrap();
+        construct_hierarchial_weighed(&document, [2.0, 1.0, 0.0, 0.0])
+    }
+
+    #[test]
+    fn prune_isolated() {
+        let document = NddFile::parse("cat dog\nbird".as_bytes()).unwrap();
+        let mut g = construct_sentence_count(&document);
+        assert_eq!(g.prune_isolated(), 1);
+        assert!(g.vertices().eq(vec!["cat".to_string(), "dog".to_string()]));
+        assert_eq!(g.get("cat", "dog"), Ok(&Some(1)));
+        assert_eq!(g.get("dog", "dog"), Ok(&Some(1)));
+        assert_eq!(g.prune_isolated(), 0);
+    }
+
+    #[test]
+    fn fold_edges() {
+        let g = test_graph();
+        let sum = g.fold_edges(0.0, |acc, (_, _, e)| acc + e);
+        assert_eq!(sum, g.edges().map(|(_, _, e)| e).sum::<f32>());
+        let count = g.fold_edges(0, |acc, (row, col, _)| {
+            assert!(row >= col);
+            acc + 1
+        });
+        assert_eq!(count, g.edges().count());
+    }
+
+    #[test]
+    fn weighted_degree() {
+        let g = weighted(&[("cat", "dog", 2.0), ("dog", "ox", 0.5), ("ox", "ox", 1.5)]);
+        assert_eq!(g.weighted_degree("cat"), Some(2.0));
+        assert_eq!(g.weighted_degree("dog"), Some(2.5));
+        assert_eq!(g.weighted_degree("ox"), Some(3.5));
+        assert_eq!(g.weighted_degree("owl"), None);
+    }
+
+    #[test]
+    fn vertex_index() {
+        let g = test_graph();
+        for (i, v) in g.vertices().enumerate() {
+            assert_eq!(g.vertex_index(&v), Some(i));
+        }
+        assert_eq!(g.vertex_index("owl"), None);
+    }
+
+    #[test]
+    fn clone_structure_with() {
+        let g = weighted(&[("cat", "dog", 1.0), ("dog", "ox", 2.0), ("ox", "ox", 1.0)]);
+        let empty: AMGraph<usize> = g.clone_structure_with();
+        assert!(empty.vertices().eq(g.vertices()));
+        assert_eq!(empty.edges().count(), 0);
+        assert_eq!(empty.get("cat", "dog"), Ok(&None));
+    }
+
+    #[test]
+    fn jaccard_similarity() {
+        let g = weighted(&[
+            ("cat", "fur", 1.0),
+            ("cat", "pet", 1.0),
+            ("cat", "paw", 1.0),
+            ("dog", "pet", 1.0),
+            ("dog", "paw", 1.0),
+            ("dog", "bark", 1.0),
+            ("dog", "dog", 1.0),
+            ("owl", "owl", 1.0),
+        ]);
+        assert_eq!(g.jaccard_similarity("cat", "dog"), Some(0.5));
+        assert_eq!(g.jaccard_similarity("dog", "cat"), Some(0.5));
+        assert_eq!(g.jaccard_similarity("cat", "cat"), Some(1.0));
+        assert_eq!(g.jaccard_similarity("cat", "owl"), Some(0.0));
+        assert_eq!(g.jaccard_similarity("owl", "owl"), Some(0.0));
+        assert_eq!(g.jaccard_similarity("cat", "fish"), None);
+    }
+
+    #[test]
+    fn filter_vertices() {
+        let g = weighted(&[
+            ("cat", "dog", 1.0),
+            ("dog", "ox", 2.0),
+            ("bird", "cat", 3.0),
+            ("ox", "ox", 1.0),
+        ]);
+        let filtered = g.filter_vertices(|v| v.len() >= 3);
+        filtered.assert_symmetric();
+        assert!(filtered.vertices().eq(vec!["bird", "cat", "dog"]));
+        let edges: Vec<_> = filtered.edges().map(|(v1, v2, &e)| (v1, v2, e)).collect();
+        let expected = vec![
+            ("cat".to_string(), "bird".to_string(), 3.0),
+            ("dog".to_string(), "cat".to_string(), 1.0),
+        ];
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn ego_network() {
+        let g = weighted(&[
+            ("cat", "dog", 1.0),
+            ("dog", "bird", 1.0),
+            ("bird", "fish", 1.0),
+            ("cat", "mouse", 1.0),
+            ("cat", "cat", 1.0),
+            ("owl", "owl", 1.0),
+        ]);
+        let ego = g.ego_network("cat", 1).unwrap();
+        ego.assert_symmetric();
+        assert!(ego.vertices().eq(vec!["cat", "dog", "mouse"]));
+        assert_eq!(ego.edges().count(), 3);
+        assert_eq!(ego.get("dog", "mouse"), Ok(&None));
+        let ego = g.ego_network("cat", 2).unwrap();
+        assert!(ego.vertices().eq(vec!["bird", "cat", "dog", "mouse"]));
+        assert_eq!(ego.get("bird", "dog"), Ok(&Some(1.0)));
+        assert!(g.ego_network("owl", 3).unwrap().vertices().eq(vec!["owl"]));
+        assert!(g.ego_network("cow", 1).is_none());
+    }
+
+    #[test]
+    fn intersection() {
+        let g1 = weighted(&[
+            ("cat", "dog", 2.0),
+            ("dog", "bird", 1.0),
+            ("cat", "cat", 1.0),
+        ]);
+        let g2 = weighted(&[
+            ("dog", "cat", 3.0),
+            ("cat", "bird", 1.0),
+            ("dog", "fish", 1.0),
+        ]);
+        let g = g1.intersection(&g2);
+        g.assert_symmetric();
+        assert!(g.vertices().eq(vec!["bird", "cat", "dog"]));
+        let edges: Vec<_> = g.edges().map(|(v1, v2, &e)| (v1, v2, e)).collect();
+        assert_eq!(edges, vec![("dog".to_string(), "cat".to_string(), 2.0)]);
+        let g = g1.intersection_with(&g2, |a, b| a + b);
+        assert_eq!(g.get("cat", "dog"), Ok(&Some(5.0)));
+    }
+
+    #[test]
+    fn diff_edges() {
+        let old = weighted(&[
+            ("cat", "dog", 2.0),
+            ("dog", "bird", 1.0),
+            ("cat", "fish", 1.0),
+        ]);
+        let new = weighted(&[
+            ("cat", "dog", 3.0),
+            ("dog", "bird", 1.0),
+            ("bird", "owl", 0.5),
+        ]);
+        let diff = new.diff_edges(&old);
+        let s = |v: &str| v.to_string();
+        assert_eq!(diff.added, [(s("owl"), s("bird"), 0.5)]);
+        assert_eq!(diff.removed, [(s("fish"), s("cat"), 1.0)]);
+        assert_eq!(diff.changed, [(s("dog"), s("cat"), 2.0, 3.0)]);
+        assert_eq!(new.diff_edges(&new).changed, []);
+    }
+
+    #[test]
+    fn scale_edges() {
+        let mut g = weighted(&[
+            ("cat", "dog", 2.0),
+            ("dog", "bird", 1.0),
+            ("fish", "fish", 3.0),
+        ]);
+        let before: Vec<_> = g.edges().map(|(v1, v2, &e)| (v1, v2, e * 0.5)).collect();
+        g.scale_edges(0.5);
+        let after: Vec<_> = g.edges().map(|(v1, v2, &e)| (v1, v2, e)).collect();
+        assert_eq!(after, before);
+        assert_eq!(g.get("cat", "fish"), Ok(&None));
+    }
+
+    #[test]
+    fn sample_edges() {
+        let rng = &mut Pcg64Mcg::seed_from_u64(0);
+        let g = test_graph();
+        let count = g.edges().count();
+        let mut all: Vec<_> = g.sample_edges(count + 5, rng);
+        all.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        let mut expected: Vec<_> = g.edges().collect();
+        expected.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        assert_eq!(all, expected);
+        let sample = g.sample_edges(3, rng);
+        assert_eq!(sample.len(), 3);
+        assert!(sample.iter().all(|e| expected.contains(e)));
+        assert!(g.sample_edges(0, rng).is_empty());
+        let mut max = g.sample_edges(usize::MAX, rng);
+        max.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        assert_eq!(max, expected);
+    }
+
+    #[test]
+    fn to_edge_list() {
+        let document = NddFile::parse("cat dog\ncat dog bird".as_bytes()).unwrap();
+        let g = construct_sentence_count(&document);
+        let list = g.to_edge_list();
+        assert_eq!(list.len(), g.edges().count());
+        for ((v1, v2, w), (e1, e2, &e)) in list.into_iter().zip(g.edges()) {
+            assert_eq!((v1, v2, w), (e1, e2, e as f32));
+        }
+    }
+
+    #[test]
+    fn pagerank() {
+        let g = weighted(&[
+            ("hub", "a", 1.0),
+            ("hub", "b", 1.0),
+            ("hub", "c", 1.0),
+            ("c", "d", 1.0),
+            ("hub", "hub", 5.0),
+            ("lone", "lone", 1.0),
+        ]);
+        let ranks: HashMap<String, f32> = g.pagerank(0.85, 100, 1e-6).into_iter().collect();
+        assert!((ranks.values().sum::<f32>() - 1.0).abs() < 1e-4);
+        assert!(ranks["hub"] > ranks["c"]);
+        assert!(ranks["c"] > ranks["d"]);
+        assert!(ranks["d"] > ranks["lone"]);
+        assert!((ranks["a"] - ranks["b"]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn contains_edge() {
+        let g = weighted(&[("cat", "dog", 2.0), ("dog", "bird", 1.0)]);
+        assert!(g.contains_edge("cat", "dog"));
+        assert!(g.contains_edge("dog", "cat"));
+        assert!(!g.contains_edge("cat", "bird"));
+        assert!(!g.contains_edge("cat", "fish"));
+    }
+
+    #[test]
+    fn get_checked() {
+        let mut g = weighted(&[("cat", "dog", 2.0)]);
+        assert_eq!(g.get_checked("dog", "cat"), Ok(&Some(2.0)));
+        let missing = |v: &[&str]| MissingVertex {
+            vertices: v.iter().map(|s| s.to_string()).collect(),
+        };
+        assert_eq!(g.get_checked("cat", "fish"), Err(missing(&["fish"])));
+        assert_eq!(g.get_checked("owl", "dog"), Err(missing(&["owl"])));
+        assert_eq!(g.get_checked("owl", "fish"), Err(missing(&["owl", "fish"])));
+        assert_eq!(g.get_checked("owl", "owl"), Err(missing(&["owl"])));
+        let err = g.get_mut_checked("cat", "fish").unwrap_err();
+        assert_eq!(err.to_string(), "vertex not in graph: fish");
+    }
+
+    #[test]
+    fn betweenness_centrality() {
+        let g = weighted(&[
+            ("a1", "a2", 1.0),
+            ("a1", "a3", 1.0),
+            ("a2", "a3", 1.0),
+            ("b1", "b2", 1.0),
+            ("b1", "b3", 1.0),
+            ("b2", "b3", 1.0),
+            ("a3", "bridge", 1.0),
+            ("bridge", "b3", 1.0),
+            ("bridge", "bridge", 5.0),
+        ]);
+        let c: HashMap<String, f32> = g.betweenness_centrality().into_iter().collect();
+        // The bridge lies on the paths between each of the 3 * 3 pairs of opposite verticies.
+        assert_eq!(c["bridge"], 9.0);
+        // a3 lies on the paths from a1 and a2 to the bridge and the 3 verticies beyond it.
+        assert_eq!(c["a3"], 8.0);
+        assert_eq!(c["a1"], 0.0);
+        assert!(c.iter().all(|(v, &x)| v == "bridge" || x < c["bridge"]));
+        let square = weighted(&[
+            ("a", "b", 1.0),
+            ("b", "c", 1.0),
+            ("c", "d", 1.0),
+            ("d", "a", 1.0),
+        ]);
+        let c = square.betweenness_centrality();
+        assert!(c.iter().all(|&(_, x)| x == 0.5));
+    }
+
+    #[test]
+    fn degree_assortativity() {
+        let star = weighted(&[
+            ("hub", "a", 1.0),
+            ("hub", "b", 1.0),
+            ("hub", "c", 1.0),
+            ("hub", "hub", 1.0),
+        ]);
+        assert!((star.degree_assortativity() + 1.0).abs() < 1e-6);
+        // A triangle and a separate edge: every edge joins verticies of equal degree.
+        let g = weighted(&[
+            ("a", "b", 1.0),
+            ("b", "c", 1.0),
+            ("c", "a", 1.0),
+            ("x", "y", 1.0),
+        ]);
+        assert!((g.degree_assortativity() - 1.0).abs() < 1e-6);
+        let path = weighted(&[
+            ("a", "b", 1.0),
+            ("b", "c", 1.0),
+            ("c", "d", 1.0),
+            ("d", "e", 1.0),
+        ]);
+        assert!(path.degree_assortativity() < 0.0);
+        assert!(weighted(&[("a", "b", 0.0)]).degree_assortativity().is_nan());
+        assert!(weighted(&[("a", "b", 1.0), ("c", "d", 1.0)])
+            .degree_assortativity()
+            .is_nan());
+    }
+
+    #[test]
+    fn total_weight() {
+        let g = weighted(&[("cat", "dog", 2.0), ("dog", "ox", 0.5), ("ox", "ox", 1.5)]);
+        assert_eq!(g.total_weight(), 4.0);
+        assert_eq!(
+            g.total_weight(),
+            g.edges().map(|(_, _, e)| e.value()).sum::<f32>()
+        );
+        assert_eq!(AMGraph::<f32>::default().total_weight(), 0.0);
+    }
+
+    #[test]
+    fn remove_edge() {
+        let mut g = weighted(&[("cat", "dog", 2.0), ("dog", "bird", 1.0)]);
+        assert_eq!(g.remove_edge("dog", "cat"), Some(2.0));
+        assert_eq!(g.get("cat", "dog"), Ok(&None));
+        assert_eq!(g.remove_edge("cat", "dog"), None);
+        assert_eq!(g.remove_edge("cat", "fish"), None);
+        assert_eq!(g.get("bird", "dog"), Ok(&Some(1.0)));
+    }
+
+    #[test]
+    fn extreme_edges() {
+        let g = weighted(&[
+            ("cat", "dog", 2.0),
+            ("dog", "bird", 7.0),
+            ("fish", "fish", 0.5),
+            ("cat", "bird", 7.0),
+        ]);
+        let s = |v: &str| v.to_string();
+        assert_eq!(g.max_edge(), Some((s("cat"), s("bird"), 7.0)));
+        assert_eq!(g.min_edge(), Some((s("fish"), s("fish"), 0.5)));
+        let empty = weighted(&[]);
+        assert_eq!(empty.max_edge(), None);
+        assert_eq!(empty.min_edge(), None);
+    }
+
+    #[test]
+    fn threshold_edges_logged() {
+        let mut g = weighted(&[
+            ("cat", "dog", 2.0),
+            ("dog", "bird", 0.5),
+            ("cat", "cat", 0.2),
+            ("cat", "bird", 1.0),
+        ]);
+        let s = |v: &str| v.to_string();
+        let removed = g.threshold_edges_logged(1.0);
+        assert_eq!(
+            removed,
+            [(s("cat"), s("cat"), 0.2), (s("dog"), s("bird"), 0.5)]
+        );
+        assert!(!g.contains_edge("cat", "cat"));
+        assert!(!g.contains_edge("dog", "bird"));
+        assert_eq!(
+            g.to_edge_list(),
+            [(s("cat"), s("bird"), 1.0), (s("dog"), s("cat"), 2.0)]
+        );
+        assert_eq!(g.threshold_edges_logged(1.0), []);
+    }
+
+    #[test]
+    fn edges_above() {
+        let document = NddFile::parse("cat dog\ncat dog bird\nbird".as_bytes()).unwrap();
+        let g = construct_sentence_count(&document);
+        let mut strong: Vec<_> = g.edges_above(1.0).map(|(v1, v2, &e)| (v1, v2, e)).collect();
+        strong.sort();
+        let expected = vec![
+            ("bird".to_string(), "bird".to_string(), 2),
+            ("cat".to_string(), "cat".to_string(), 2),
+            ("dog".to_string(), "cat".to_string(), 2),
+            ("dog".to_string(), "dog".to_string(), 2),
+        ];
+        assert_eq!(strong, expected);
+    }
+
+    #[test]
+    fn neighbors_sorted() {
+        let g = weighted(&[
+            ("cat", "dog", 2.0),
+            ("cat", "bird", 5.0),
+            ("cat", "fish", 0.5),
+            ("cat", "cat", 9.0),
+            ("cat", "owl", 2.0),
+            ("dog", "bird", 7.0),
+        ]);
+        let s = |v: &str| v.to_string();
+        assert_eq!(
+            g.neighbors_sorted("cat"),
+            [
+                (s("bird"), 5.0),
+                (s("dog"), 2.0),
+                (s("owl"), 2.0),
+                (s("fish"), 0.5)
+            ]
+        );
+        assert_eq!(g.neighbors_sorted("mouse"), []);
+    }
+
+    #[test]
+    fn clustering_coefficient() {
+        let triangle = construct_sentence_count(&NddFile::parse("a b c".as_bytes()).unwrap());
+        assert_eq!(triangle.clustering_coefficient("a"), Some(1.0));
+        let star = NddFile::parse("hub x\nhub y\nhub z".as_bytes()).unwrap();
+        let star = construct_sentence_count(&star);
+        assert_eq!(star.clustering_coefficient("hub"), Some(0.0));
+        assert_eq!(star.clustering_coefficient("x"), Some(0.0));
+        assert_eq!(star.clustering_coefficient("w"), None);
+    }
+
+    #[test]
+    fn relabel_to_indices() {
+        let g = test_graph();
+        let map = g.map.clone();
+        let (labels, edges) = g.clone().relabel_to_indices();
+        assert!(labels.windows(2).all(|w| w[0] < w[1]));
+        for (i, l) in labels.iter().enumerate() {
+            assert_eq!(map.get(&**l), Some(i));
+            for (j, m) in labels.iter().enumerate() {
+                assert_eq!(&edges[(i, j)], g.get(l, m).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric() {
+        let mut g = test_graph();
+        assert!(g.is_symmetric());
+        g.assert_symmetric();
+        g.edges.push(None);
+        assert!(!g.is_symmetric());
+        g.edges.0.truncate(g.edges.len() - 2);
+        assert!(!g.is_symmetric());
+    }
+
+    #[test]
+    fn weight_histogram() {
+        let g = weighted(&[
+            ("a", "b", 1.0),
+            ("a", "c", 2.0),
+            ("b", "c", 4.0),
+            ("c", "c", 4.0),
+        ]);
+        assert_eq!(g.weight_histogram(3), vec![1, 1, 2]);
+        let flat = weighted(&[("a", "b", 2.0), ("b", "c", 2.0)]);
+        assert_eq!(flat.weight_histogram(3), vec![2, 0, 0]);
+        assert_eq!(flat.weight_histogram(0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn diagonal() {
+        let g = test_graph();
+        // Each vertex gets 2.0 for every sentence it appears in.
+        assert_eq!(g.diagonal(), array![4.0, 2.0, 4.0, 2.0]);
+        let g = weighted(&[
+            ("cat", "cat", 3.0),
+            ("cat", "dog", 1.0),
+            ("fish", "fish", 0.5),
+        ]);
+        assert_eq!(g.diagonal(), array![3.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn self_loops() {
+        let g = test_graph();
+        let loops: Vec<_> = g.self_loops().collect();
+        assert_eq!(loops.len(), g.len());
+        assert!(loops.iter().map(|(v, _)| v.clone()).eq(g.vertices()));
+        let (_, &dog) = loops.iter().find(|(v, _)| v == "dog").unwrap();
+        assert_eq!(dog, 4.0);
+    }
+}
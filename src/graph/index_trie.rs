@@ -5,7 +5,7 @@
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::{min, Ordering},
-    iter::{once, FromIterator, FusedIterator},
+    iter::{once, FromIterator, FusedIterator, Take},
 };
 
 /// An indexable trie, suitable for use as a bijective map.
@@ -75,6 +75,166 @@ impl IndexTrie {
         k.get(self)
     }
 
+    /// Builds a trie from strings that are sorted and contain no duplicates.
+    ///
+    /// This builds each node once, rather than splitting nodes as strings are inserted, so is
+    /// faster than collecting into a trie for large inputs.
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if the strings are not strictly increasing.
+    pub fn from_sorted<S: AsRef<str>>(keys: &[S]) -> Self {
+        // `keys` are sorted and distinct, so each group of keys sharing a first byte is
+        // contiguous, and its common prefix is the common prefix of its first and last keys.
+        fn build(keys: &[&[u8]]) -> Vec<Node> {
+            let mut nodes = Vec::new();
+            let mut rest = keys;
+            if let Some(k) = rest.first() {
+                if k.is_empty() {
+                    nodes.push(Node::Leaf { rest: Vec::new() });
+                    rest = &rest[1..];
+                }
+            }
+            while let Some(first) = rest.first() {
+                let end = rest
+                    .iter()
+                    .position(|k| k[0] != first[0])
+                    .unwrap_or(rest.len());
+                let (group, remaining) = rest.split_at(end);
+                if let [k] = group {
+                    nodes.push(Node::Leaf { rest: k.to_vec() });
+                } else {
+                    let c_len = common_prefix(first, group[end - 1]);
+                    let children: Vec<&[u8]> = group.iter().map(|k| &k[c_len..]).collect();
+                    nodes.push(Node::NonLeaf {
+                        prefix: first[..c_len].to_vec(),
+                        children: build(&children),
+                        len: end,
+                    });
+                }
+                rest = remaining;
+            }
+            nodes
+        }
+
+        let keys: Vec<&[u8]> = keys.iter().map(|k| k.as_ref().as_bytes()).collect();
+        debug_assert!(
+            keys.windows(2).all(|w| w[0] < w[1]),
+            "keys must be sorted and distinct"
+        );
+        IndexTrie {
+            roots: build(&keys),
+            len: keys.len(),
+        }
+    }
+
+    /// Returns an iterator over the contained strings `s` with `start <= s < end`, in sorted order.
+    ///
+    /// The bounds need not be in the trie. The iterator is positioned at `start` by descending the
+    /// trie, so strings before it are not visited. If `start > end`, nothing is returned.
+    pub fn range(&self, start: &str, end: &str) -> Take<IndexTrieIterator<'_>> {
+        let lo = self.rank(start);
+        let hi = self.rank(end).max(lo);
+        self.iter_from(lo).take(hi - lo)
+    }
+
+    /// Returns the number of contained strings less than `k`.
+    ///
+    /// This is the index of `k` if it is contained, and the index it would be inserted at
+    /// otherwise.
+    fn rank(&self, k: &str) -> usize {
+        fn rank_inner(nodes: &[Node], k: &[u8], mut i: usize) -> usize {
+            for n in nodes {
+                match n {
+                    Node::Leaf { rest } => {
+                        if k > &rest[..] {
+                            i += 1;
+                        } else {
+                            return i;
+                        }
+                    }
+                    Node::NonLeaf {
+                        prefix,
+                        children,
+                        len,
+                    } => match k[..min(k.len(), prefix.len())].cmp(prefix) {
+                        Ordering::Greater => i += len,
+                        Ordering::Equal => return rank_inner(children, &k[prefix.len()..], i),
+                        // `k` is before, or a proper prefix of, every string under this node.
+                        Ordering::Less => return i,
+                    },
+                }
+            }
+            i
+        }
+
+        rank_inner(&self.roots, k.as_bytes(), 0)
+    }
+
+    /// Returns an iterator starting at the string with index `i`.
+    fn iter_from(&self, mut i: usize) -> IndexTrieIterator<'_> {
+        let mut state = vec![IterItem::Root(self, 0)];
+        let mut nodes: &[Node] = &self.roots;
+        loop {
+            // Skip over whole subtrees before index `i`.
+            let mut j = 0;
+            while j < nodes.len() && i >= nodes[j].len() {
+                i -= nodes[j].len();
+                j += 1;
+            }
+            let next = match state.last_mut() {
+                Some(IterItem::Root(_, next)) | Some(IterItem::Prefix(_, next)) => next,
+                None => unreachable!(),
+            };
+            match nodes.get(j) {
+                Some(Node::NonLeaf {
+                    prefix: _,
+                    children,
+                    len: _,
+                }) => {
+                    // The parent list resumes after this node once its children are exhausted.
+                    *next = j + 1;
+                    state.push(IterItem::Prefix(&nodes[j], 0));
+                    nodes = children;
+                }
+                // A leaf, or past the end of the trie.
+                _ => {
+                    *next = j;
+                    return IndexTrieIterator { state };
+                }
+            }
+        }
+    }
+
+    /// Returns the strings only in `self` and the strings only in `other`, in sorted order.
+    ///
+    /// Both tries are iterated in sorted order together, so this takes linear time.
+    ///
+    /// The return type is of the format (only in `self`, only in `other`).
+    pub fn difference(&self, other: &IndexTrie) -> (Vec<String>, Vec<String>) {
+        let mut only_self = Vec::new();
+        let mut only_other = Vec::new();
+        let mut a = self.into_iter().peekable();
+        let mut b = other.into_iter().peekable();
+        loop {
+            let order = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) => x.cmp(y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => break,
+            };
+            match order {
+                Ordering::Less => only_self.push(a.next().unwrap()),
+                Ordering::Greater => only_other.push(b.next().unwrap()),
+                Ordering::Equal => {
+                    a.next();
+                    b.next();
+                }
+            }
+        }
+        (only_self, only_other)
+    }
+
     /// Inserts a string into the trie.
     ///
     /// Returns true if the string was added, and false if the string was already in the trie.
@@ -552,6 +712,41 @@ mod tests {
         assert_eq!(t.into_iter().eq(c.iter().map(|s| s.to_string())), true);
     }
 
+    #[test]
+    fn from_sorted() {
+        let t = IndexTrie::from_sorted(&expected_contents());
+        assert_eq!(t, test_trie());
+        let words = ["", "a", "ab", "abc", "b", "ba", "bb", "c"];
+        assert_eq!(
+            IndexTrie::from_sorted(&words),
+            words.iter().cloned().collect()
+        );
+        assert_eq!(IndexTrie::from_sorted::<&str>(&[]), IndexTrie::new());
+    }
+
+    #[test]
+    fn difference() {
+        let other: IndexTrie = vec!["aa", "aaa", "aacb", "b"].into_iter().collect();
+        let (only_self, only_other) = test_trie().difference(&other);
+        assert_eq!(only_self, vec!["aaaaa", "aaaab", "aabb", "aacee"]);
+        assert_eq!(only_other, vec!["aa", "b"]);
+        let (only_self, only_other) = other.difference(&other);
+        assert!(only_self.is_empty() && only_other.is_empty());
+    }
+
+    #[test]
+    fn range() {
+        let t = test_trie();
+        let range = |start, end| t.range(start, end).collect::<Vec<_>>();
+        assert_eq!(range("aaaa", "aacb"), vec!["aaaaa", "aaaab", "aabb"]);
+        assert_eq!(range("aaaab", "aacc"), vec!["aaaab", "aabb", "aacb"]);
+        assert_eq!(range("aacb", "b"), vec!["aacb", "aacee"]);
+        assert_eq!(range("", "z"), expected_contents());
+        assert_eq!(t.range("aab", "aab").len(), 0);
+        assert_eq!(t.range("aacb", "aaa").len(), 0);
+        assert_eq!(t.range("b", "z").len(), 0);
+    }
+
     #[test]
     fn iter_len() {
         let t = test_trie();
@@ -0,0 +1,164 @@
+//! Persistence of graphs in a compact binary format.
+//!
+//! The binary format is much smaller and faster to read and write than JSON for the dense edge
+//! matrices produced by graph construction. Sparse graphs over a shared corpus vocabulary can be
+//! stored more compactly with `write_with_shared_vocab`, which stores the vocabulary once and
+//! only the present edges of each graph.
+
+use crate::graph::{Graph, IndexMap};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// A graph stored against a shared vocabulary.
+///
+/// `vertices` are the indices of the verticies of the graph in the vocabulary, in order, and
+/// `edges` are the present edges, with verticies given by their position in `vertices`.
+#[derive(Serialize, Deserialize)]
+struct SharedGraph<E> {
+    vertices: Vec<u32>,
+    edges: Vec<(u32, u32, E)>,
+}
+
+/// Writes a graph in the binary format.
+pub fn write_binary<E: Serialize, W: Write>(graph: &Graph<E>, out: W) -> io::Result<()> {
+    bincode::serialize_into(out, graph).map_err(|e| into_io_error(*e))
+}
+
+/// Reads a graph written by `write_binary`.
+///
+/// Returns an error of kind `InvalidData` if the input is not a valid graph.
+pub fn read_binary<E: DeserializeOwned, R: Read>(input: R) -> io::Result<Graph<E>> {
+    bincode::deserialize_from(input).map_err(|e| into_io_error(*e))
+}
+
+/// Writes graphs that share a vocabulary, storing the vocabulary only once.
+///
+/// The sorted union of the verticies of the graphs is written to `vocab_path`. Each graph is
+/// written to `graphs_path` as the indices of its verticies in the vocabulary and its present
+/// edges, so absent edges take no space. Read the graphs back with `read_with_shared_vocab`.
+pub fn write_with_shared_vocab<E: Serialize>(
+    graphs: &[Graph<E>],
+    vocab_path: &Path,
+    graphs_path: &Path,
+) -> io::Result<()> {
+    let vocab: IndexMap = graphs.iter().flat_map(|g| g.vertices()).collect();
+    let terms: Vec<String> = vocab.into_iter().collect();
+    let mut vocab_out = BufWriter::new(File::create(vocab_path)?);
+    bincode::serialize_into(&mut vocab_out, &terms).map_err(|e| into_io_error(*e))?;
+    vocab_out.flush()?;
+    let mut out = BufWriter::new(File::create(graphs_path)?);
+    bincode::serialize_into(&mut out, &graphs.len()).map_err(|e| into_io_error(*e))?;
+    for g in graphs {
+        let shared = SharedGraph {
+            vertices: g
+                .vertices()
+                .map(|v| vocab.get(&*v).unwrap() as u32)
+                .collect(),
+            edges: g.fold_edges(Vec::new(), |mut acc, (row, col, e)| {
+                acc.push((row as u32, col as u32, e));
+                acc
+            }),
+        };
+        bincode::serialize_into(&mut out, &shared).map_err(|e| into_io_error(*e))?;
+    }
+    out.flush()
+}
+
+/// Reads graphs written by `write_with_shared_vocab`.
+///
+/// Returns an error of kind `InvalidData` if the files are not valid, including if a graph refers
+/// to a vertex that is not in the vocabulary.
+pub fn read_with_shared_vocab<E: DeserializeOwned>(
+    vocab_path: &Path,
+    graphs_path: &Path,
+) -> io::Result<Vec<Graph<E>>> {
+    let terms: Vec<String> = bincode::deserialize_from(BufReader::new(File::open(vocab_path)?))
+        .map_err(|e| into_io_error(*e))?;
+    let mut input = BufReader::new(File::open(graphs_path)?);
+    let len: usize = bincode::deserialize_from(&mut input).map_err(|e| into_io_error(*e))?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "vertex index out of range");
+    let mut res = Vec::new();
+    for _ in 0..len {
+        let shared: SharedGraph<E> =
+            bincode::deserialize_from(&mut input).map_err(|e| into_io_error(*e))?;
+        let names = shared
+            .vertices
+            .iter()
+            .map(|&i| {
+                terms
+                    .get(i as usize)
+                    .map(String::as_str)
+                    .ok_or_else(invalid)
+            })
+            .collect::<io::Result<Vec<&str>>>()?;
+        let mut graph = Graph::new(names.iter().cloned().collect());
+        for (row, col, e) in shared.edges {
+            let (v1, v2) = match (names.get(row as usize), names.get(col as usize)) {
+                (Some(v1), Some(v2)) => (v1, v2),
+                _ => return Err(invalid()),
+            };
+            *graph.get_mut(v1, v2).unwrap() = Some(e);
+        }
+        res.push(graph);
+    }
+    Ok(res)
+}
+
+fn into_io_error(err: bincode::ErrorKind) -> io::Error {
+    match err {
+        bincode::ErrorKind::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        construct::construct_hierarchial_weighed,
+        input::{InputFormat, NddFile},
+    };
+
+    #[test]
+    fn round_trip() {
+        let document = NddFile::parse("cat dog\ndog bird\n\nbird fish".as_bytes()).unwrap();
+        let graph = construct_hierarchial_weighed(&document, [2.0, 1.0, 0.5, 0.0]);
+        let mut buf = Vec::new();
+        write_binary(&graph, &mut buf).unwrap();
+        assert!(buf.len() < serde_json::to_vec(&graph).unwrap().len());
+        let read: Graph<f32> = read_binary(&buf[..]).unwrap();
+        assert!(read.vertices().eq(graph.vertices()));
+        assert_eq!(read.to_edge_list(), graph.to_edge_list());
+        let err = read_binary::<f32, _>(&buf[..buf.len() / 2]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn shared_vocab_round_trip() {
+        let graphs: Vec<Graph<f32>> = ["cat dog\ndog bird\n\nbird fish", "cat owl", "dog fish"]
+            .iter()
+            .map(|text| {
+                let document = NddFile::parse(text.as_bytes()).unwrap();
+                construct_hierarchial_weighed(&document, [2.0, 1.0, 0.5, 0.0])
+            })
+            .collect();
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let vocab_path = dir.join(format!("fact_graph_vocab_{}", id));
+        let graphs_path = dir.join(format!("fact_graph_graphs_{}", id));
+        write_with_shared_vocab(&graphs, &vocab_path, &graphs_path).unwrap();
+        let read = read_with_shared_vocab::<f32>(&vocab_path, &graphs_path);
+        std::fs::remove_file(&vocab_path).unwrap();
+        std::fs::remove_file(&graphs_path).unwrap();
+        let read = read.unwrap();
+        assert_eq!(read.len(), graphs.len());
+        for (r, g) in read.iter().zip(&graphs) {
+            assert!(r.vertices().eq(g.vertices()));
+            assert_eq!(r.to_edge_list(), g.to_edge_list());
+        }
+    }
+}
@@ -7,9 +7,45 @@ newtype_deref! {
     /// Type representing a lower triangualar matrix using a `Vec`.
     /// Provides 2 dimensional indexing for the `Vec`
     #[derive(Default, Clone, Debug, Serialize, Deserialize)]
-    pub(crate) struct LowerTriangular<T>(pub(crate) Vec<T>);
+    pub struct LowerTriangular<T>(pub Vec<T>);
 }
 
+impl<T> LowerTriangular<T> {
+    /// Returns the number of rows (and columns) of the matrix.
+    pub fn dim(&self) -> usize {
+        let mut n = 0;
+        while (n * (n + 1)) / 2 < self.0.len() {
+            n += 1;
+        }
+        debug_assert_eq!((n * (n + 1)) / 2, self.0.len(), "storage is not triangular");
+        n
+    }
+
+    /// Returns an iterator over the elements in storage order, which is row-major.
+    ///
+    /// The return type is of the format ((row, column), element), where row >= column.
+    pub fn iter_row_major(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        (0..self.dim())
+            .flat_map(|row| (0..=row).map(move |col| (row, col)))
+            .zip(self.0.iter())
+    }
+
+    /// Returns an iterator over the elements in column-major order.
+    ///
+    /// Each column is visited from the diagonal down, so this visits the upper triangular
+    /// transpose of the matrix in row-major order.
+    ///
+    /// The return type is of the format ((row, column), element), where row >= column.
+    pub fn iter_column_major(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let n = self.dim();
+        (0..n)
+            .flat_map(move |col| (col..n).map(move |row| (row, col)))
+            .map(move |i| (i, &self[i]))
+    }
+}
+
+/// Indexing is symmetric: `(row, column)` and its transpose `(column, row)` refer to the same
+/// element.
 impl<T> Index<(usize, usize)> for LowerTriangular<T> {
     type Output = T;
 
@@ -27,3 +63,24 @@ impl<T> IndexMut<(usize, usize)> for LowerTriangular<T> {
         &mut self.0[(row * (row + 1)) / 2 + col]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn column_major() {
+        let m = LowerTriangular((0..10).collect::<Vec<usize>>());
+        assert_eq!(m.dim(), 4);
+        let columns: Vec<_> = m.iter_column_major().map(|(i, _)| i).collect();
+        assert_eq!(columns[..5], [(0, 0), (1, 0), (2, 0), (3, 0), (1, 1)]);
+        let rows: HashSet<_> = m.iter_row_major().map(|(i, &e)| (i, e)).collect();
+        let columns: HashSet<_> = m.iter_column_major().map(|(i, &e)| (i, e)).collect();
+        assert_eq!(rows.len(), 10);
+        assert_eq!(rows, columns);
+        for ((row, col), &e) in m.iter_row_major() {
+            assert_eq!(m[(col, row)], e);
+        }
+    }
+}
@@ -1,36 +1,371 @@
 //! Types and parsing functions for input files.
 //!
-//! Contains the `Document` type as the result of parsing input files.
+//! Contains the `Document` type as the result of parsing input files, and the `Corpus` type for
+//! collections of named documents.
 //!
 //! Each supported input format provides a type impementing `InputFormat` which can be used to be
 //! generic over the input format.
 
+use crate::{graph::IndexMap, preprocess::Preprocessor};
+use flate2::read::GzDecoder;
+use itertools::Itertools;
+use rayon::prelude::*;
 use std::{
-    io::{self, BufRead},
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Write},
+    iter::{self, FromIterator},
+    mem,
     ops::{Deref, DerefMut},
+    path::Path,
 };
 
 newtype_deref! {
     /// Type representing a document.
     /// A document is a list of paragraphs.
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq)]
     pub struct Document(pub Vec<Paragraph>);
 
     /// Type representing a paragraph.
     /// A paragraph is a list of sentences.
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq)]
     pub struct Paragraph(pub Vec<Sentence>);
 
     /// Type representing a Sentence.
     /// A sentence is a list of terms.
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq)]
     pub struct Sentence(pub Vec<Term>);
 
     /// Type representing a term.
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq)]
     pub struct Term(pub String);
 }
 
+impl Document {
+    /// Starts a new paragraph at the end of the document.
+    pub fn push_paragraph(&mut self) {
+        self.push(Paragraph(Vec::new()));
+    }
+
+    /// Starts a new sentence at the end of the last paragraph, starting a paragraph if there are
+    /// none.
+    pub fn push_sentence(&mut self) {
+        if self.is_empty() {
+            self.push_paragraph();
+        }
+        self.last_mut().unwrap().push(Sentence(Vec::new()));
+    }
+
+    /// Appends a term to the last sentence, starting a sentence if the last paragraph has none.
+    pub fn push_term(&mut self, term: Term) {
+        if !matches!(self.last(), Some(p) if !p.is_empty()) {
+            self.push_sentence();
+        }
+        self.last_mut().unwrap().last_mut().unwrap().push(term);
+    }
+}
+
+/// Classification of a term by the kinds of characters it contains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TermKind {
+    /// A term without digits, such as "vaccine".
+    Word,
+    /// A term with digits but no letters, such as "2020" or "3.14".
+    Number,
+    /// A term with both letters and digits, such as "covid19".
+    Mixed,
+}
+
+impl TermKind {
+    /// Classifies a term.
+    pub fn classify(term: &str) -> Self {
+        let digits = term.chars().any(char::is_numeric);
+        let letters = term.chars().any(char::is_alphabetic);
+        match (digits, letters) {
+            (false, _) => TermKind::Word,
+            (true, false) => TermKind::Number,
+            (true, true) => TermKind::Mixed,
+        }
+    }
+}
+
+/// A term along with its `TermKind`, for analyses that treat numbers and words differently.
+///
+/// # Examples
+///
+/// ```
+/// use fact_graph::input::{DocumentBuilder, TermKind};
+///
+/// let d = DocumentBuilder::new().sentence(&["covid19", "cases", "2020"]).build();
+/// let kinds: Vec<TermKind> = d[0][0].tagged().iter().map(|t| t.kind).collect();
+/// assert_eq!(kinds, [TermKind::Mixed, TermKind::Word, TermKind::Number]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaggedTerm {
+    /// The term.
+    pub term: Term,
+    /// The kind of the term.
+    pub kind: TermKind,
+}
+
+impl From<Term> for TaggedTerm {
+    fn from(term: Term) -> Self {
+        let kind = TermKind::classify(&term);
+        TaggedTerm { term, kind }
+    }
+}
+
+impl Sentence {
+    /// Returns the terms of the sentence tagged with their kind.
+    pub fn tagged(&self) -> Vec<TaggedTerm> {
+        self.iter().cloned().map(TaggedTerm::from).collect()
+    }
+}
+
+/// Builder for constructing a `Document` programmatically.
+///
+/// # Examples
+///
+/// ```
+/// use fact_graph::input::DocumentBuilder;
+///
+/// let d = DocumentBuilder::new()
+///     .sentence(&["cats", "meow"])
+///     .sentence(&["cats", "purr"])
+///     .paragraph()
+///     .sentence(&["dogs", "bark"])
+///     .build();
+/// assert_eq!(d.len(), 2);
+/// assert_eq!(d[0].len(), 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DocumentBuilder {
+    document: Document,
+}
+
+impl DocumentBuilder {
+    /// Creates a builder for an empty document.
+    pub fn new() -> Self {
+        DocumentBuilder {
+            document: Document(Vec::new()),
+        }
+    }
+
+    /// Starts a new paragraph. Does nothing if the current paragraph has no sentences.
+    pub fn paragraph(mut self) -> Self {
+        if matches!(self.document.last(), Some(p) if !p.is_empty()) {
+            self.document.push_paragraph();
+        }
+        self
+    }
+
+    /// Adds a sentence with the given terms to the current paragraph.
+    pub fn sentence(mut self, terms: &[&str]) -> Self {
+        self.document.push_sentence();
+        let sentence = self.document.last_mut().unwrap().last_mut().unwrap();
+        sentence.extend(terms.iter().map(|t| Term(t.to_string())));
+        self
+    }
+
+    /// Returns the built document.
+    pub fn build(self) -> Document {
+        self.document
+    }
+}
+
+impl Default for DocumentBuilder {
+    fn default() -> Self {
+        DocumentBuilder::new()
+    }
+}
+
+/// A collection of named documents.
+///
+/// Each document has a name and a set of arbitrary key-value metadata describing it, such as its
+/// true class. A `Corpus` can be collected from the output of `parse_dir`, which gives each
+/// document empty metadata.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Corpus {
+    documents: Vec<Document>,
+    names: Vec<String>,
+    metadata: Vec<HashMap<String, String>>,
+}
+
+impl Corpus {
+    /// Returns the number of documents in the corpus.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns `true` if the corpus contains no documents.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Returns the documents in the corpus.
+    pub fn documents(&self) -> &[Document] {
+        &self.documents
+    }
+
+    /// Returns the name of each document in the corpus.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the metadata of the `i`th document.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn metadata(&self, i: usize) -> &HashMap<String, String> {
+        &self.metadata[i]
+    }
+
+    /// Returns a mutable reference to the metadata of the `i`th document.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn metadata_mut(&mut self, i: usize) -> &mut HashMap<String, String> {
+        &mut self.metadata[i]
+    }
+
+    /// Returns an iterator over the documents in the corpus.
+    ///
+    /// The return type is of the format (name, document).
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Document)> {
+        self.names.iter().map(|n| n.as_str()).zip(&self.documents)
+    }
+
+    /// Returns a parallel iterator over the documents in the corpus.
+    ///
+    /// The return type is of the format (name, document).
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = (&str, &Document)> {
+        self.names
+            .par_iter()
+            .map(|n| n.as_str())
+            .zip(&self.documents)
+    }
+
+    /// Returns the value of the metadata field `key` for each document, or `None` for documents
+    /// without it.
+    pub fn metadata_values(&self, key: &str) -> Vec<Option<&str>> {
+        self.metadata
+            .iter()
+            .map(|m| m.get(key).map(|v| v.as_str()))
+            .collect()
+    }
+
+    /// Sets the metadata field `key` from a file of tab separated document names and values, as
+    /// read by `read_name_values`.
+    ///
+    /// Lines naming documents that are not in the corpus are ignored.
+    pub fn read_metadata<F: BufRead>(&mut self, key: &str, file: F) -> io::Result<()> {
+        let mut values = read_name_values(file)?;
+        for (name, metadata) in self.names.iter().zip(&mut self.metadata) {
+            if let Some(value) = values.remove(name) {
+                metadata.insert(key.to_string(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the metadata field `key` in the format read by `read_name_values`.
+    ///
+    /// Documents without the field are skipped.
+    pub fn write_metadata<W: Write>(&self, key: &str, mut out: W) -> io::Result<()> {
+        for (name, value) in self.names.iter().zip(self.metadata_values(key)) {
+            if let Some(value) = value {
+                writeln!(out, "{}\t{}", name, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the documents with fewer than two distinct terms.
+    ///
+    /// Such documents, for example ones made up entirely of stopwords, produce graphs without any
+    /// edges between distinct terms, and so contribute nothing to clustering.
+    pub fn degenerate_documents(&self) -> Vec<String> {
+        self.iter()
+            .filter(|(_, d)| {
+                let terms: HashSet<&str> = d
+                    .iter()
+                    .flat_map(|p| p.iter())
+                    .flat_map(|s| s.iter())
+                    .map(|t| t.as_str())
+                    .collect();
+                terms.len() < 2
+            })
+            .map(|(n, _)| n.to_string())
+            .collect()
+    }
+
+    /// Returns the set of terms used in any of the documents.
+    pub fn vocabulary(&self) -> IndexMap {
+        let mut terms: Vec<&str> = self
+            .documents
+            .par_iter()
+            .fold(HashSet::new, |mut acc, d| {
+                acc.extend(
+                    d.iter()
+                        .flat_map(|p| p.iter())
+                        .flat_map(|s| s.iter())
+                        .map(|t| t.as_str()),
+                );
+                acc
+            })
+            .reduce(HashSet::new, |mut a, b| {
+                a.extend(b);
+                a
+            })
+            .into_iter()
+            .collect();
+        terms.par_sort_unstable();
+        IndexMap::from_sorted(&terms)
+    }
+}
+
+impl FromIterator<(String, Document)> for Corpus {
+    fn from_iter<I: IntoIterator<Item = (String, Document)>>(iter: I) -> Self {
+        let (names, documents): (Vec<_>, _) = iter.into_iter().unzip();
+        let metadata = vec![HashMap::new(); names.len()];
+        Corpus {
+            documents,
+            names,
+            metadata,
+        }
+    }
+}
+
+/// Reads a file of tab separated names and values, one name per line, such as the true class of
+/// each document.
+///
+/// Blank lines are skipped, and a later line for a name replaces an earlier one. Returns an error
+/// of kind `InvalidData` if a non-blank line does not contain a tab.
+pub fn read_name_values<F: BufRead>(file: F) -> io::Result<HashMap<String, String>> {
+    let mut res = HashMap::new();
+    for (n, line) in lines(file, false).enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut columns = line.splitn(2, '\t');
+        let name = columns.next().unwrap();
+        let value = match columns.next() {
+            Some(v) => v,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {} has no value", n + 1),
+                ))
+            }
+        };
+        res.insert(name.to_string(), value.to_string());
+    }
+    Ok(res)
+}
+
 /// Trait that provides functions for handling input files of a given format.
 ///
 /// Implement this trait to add a new input file format.
@@ -58,7 +393,12 @@ pub trait InputFormat {
 ///
 /// Each non-blank line should contain a sequence of terms corresponding to a sentence in the
 /// original source document.
-/// Paragraphs are delimited by blank lines.
+/// Paragraphs are delimited by blank lines, or by marker lines if `paragraph_delimiter` is set.
+///
+/// This is the format written by `NddFile::write`, and so by the `preprocess` binary.
+///
+/// `InputFormat::parse` returns an error for input that is not valid UTF-8. Use `NddFile::read`
+/// with `lossy_utf8` set to replace invalid sequences instead.
 ///
 /// # Examples
 ///
@@ -78,17 +418,79 @@ pub trait InputFormat {
 ///    Err(_) => panic!(),
 /// };
 /// ```
-#[allow(missing_debug_implementations, missing_copy_implementations)]
-pub struct NddFile;
+#[derive(Clone, Debug, Default)]
+pub struct NddFile {
+    /// Whether invalid UTF-8 is replaced with `U+FFFD REPLACEMENT CHARACTER` rather than returning
+    /// an error.
+    pub lossy_utf8: bool,
+    /// How lines are divided into sentences.
+    pub sentence_split: SentenceSplit,
+    /// How paragraphs are delimited.
+    pub paragraph_delimiter: ParagraphDelim,
+    /// How lines over a maximum length are handled.
+    pub line_limit: LineLimit,
+}
+
+/// How `NddFile` divides lines into sentences.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SentenceSplit {
+    /// Each line is a sentence.
+    #[default]
+    OnePerLine,
+    /// Lines are split into sentences at `.`, `?`, and `!`, which are removed.
+    OnPunctuation,
+}
+
+/// How `NddFile` delimits paragraphs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ParagraphDelim {
+    /// Paragraphs are separated by blank lines.
+    #[default]
+    BlankLine,
+    /// Paragraphs are separated by lines consisting of the given marker, such as `---`, ignoring
+    /// surrounding whitespace. Blank lines are skipped.
+    Marker(String),
+}
+
+/// How `NddFile` handles lines longer than a maximum number of bytes.
+///
+/// Without a limit, a file lacking newlines is read into memory as a single line. Line endings do
+/// not count towards the length.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineLimit {
+    /// Lines may be of any length.
+    #[default]
+    Unlimited,
+    /// Lines over the given length result in an error of kind `InvalidData`.
+    Error(usize),
+    /// Lines over the given length are split into several lines of at most that length. Lines are
+    /// split at the last whitespace before the limit if there is any, so that terms are not cut.
+    Split(usize),
+}
 
 impl InputFormat for NddFile {
     fn parse<F: BufRead>(file: F) -> io::Result<Document> {
-        //let file = BufReader::new(File::open(file)?);
+        NddFile::default().read(file)
+    }
+}
+
+impl NddFile {
+    /// Parses a file in the newline delimited input format into a `Document`.
+    pub fn read<F: BufRead>(&self, file: F) -> io::Result<Document> {
         let mut res = Document(Vec::new());
         let mut in_section = false;
-        for line in file.lines() {
+        for line in bounded_lines(file, self.lossy_utf8, self.line_limit) {
             let line = line?;
-            if line.is_empty() {
+            let delimiter = match &self.paragraph_delimiter {
+                ParagraphDelim::BlankLine => line.is_empty(),
+                ParagraphDelim::Marker(marker) => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    line.trim() == marker
+                }
+            };
+            if delimiter {
                 in_section = false;
                 continue;
             }
@@ -96,12 +498,846 @@ impl InputFormat for NddFile {
                 in_section = true;
                 res.push(Paragraph(Vec::new()));
             }
-            res.last_mut().unwrap().push(Sentence(
-                line.split_whitespace()
-                    .map(|t| Term(t.to_string()))
-                    .collect(),
-            ));
+            res.last_mut().unwrap().extend(self.split_line(&line));
         }
         Ok(res)
     }
+
+    /// Returns an iterator over the sentences of a file in the newline delimited input format.
+    ///
+    /// Sentences are read one line at a time as the iterator advances, without grouping them into
+    /// paragraphs, so memory use does not grow with the size of the file. This suits construction
+    /// methods that only use sentence level co-occurrence. The sentences are those of the
+    /// paragraphs returned by `read`, in order.
+    pub fn sentences<'a, F: BufRead + 'a>(
+        &'a self,
+        file: F,
+    ) -> impl Iterator<Item = io::Result<Sentence>> + 'a {
+        bounded_lines(file, self.lossy_utf8, self.line_limit).flat_map(move |line| match line {
+            Ok(line) => {
+                let text = match &self.paragraph_delimiter {
+                    ParagraphDelim::BlankLine => !line.is_empty(),
+                    ParagraphDelim::Marker(marker) => {
+                        !line.trim().is_empty() && line.trim() != marker
+                    }
+                };
+                if text {
+                    self.split_line(&line).into_iter().map(Ok).collect()
+                } else {
+                    Vec::new()
+                }
+            }
+            Err(e) => vec![Err(e)],
+        })
+    }
+
+    /// Splits a line that is not a paragraph delimiter into sentences.
+    fn split_line(&self, line: &str) -> Vec<Sentence> {
+        let to_sentence =
+            |s: &str| Sentence(s.split_whitespace().map(|t| Term(t.to_string())).collect());
+        match self.sentence_split {
+            SentenceSplit::OnePerLine => vec![to_sentence(line)],
+            SentenceSplit::OnPunctuation => line
+                .split(&['.', '?', '!'][..])
+                .map(to_sentence)
+                .filter(|s| !s.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Writes a `Document` in the newline delimited input format.
+    ///
+    /// Terms are separated by spaces, sentences by newlines, and paragraphs by blank lines.
+    /// `NddFile::parse` is the inverse of this function for documents without empty sentences or
+    /// paragraphs, and with terms not containing whitespace.
+    pub fn write<W: Write>(doc: &Document, mut out: W) -> io::Result<()> {
+        let mut first_para = true;
+        for p in doc.iter() {
+            if first_para {
+                first_para = false;
+            } else {
+                write!(out, "\n\n")?;
+            }
+            let mut first_sent = true;
+            for s in p.iter() {
+                if first_sent {
+                    first_sent = false;
+                } else {
+                    writeln!(out)?;
+                }
+                write!(out, "{}", s.iter().map(|t| t.as_str()).join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `InputFormat` implementation for documents in a CoNLL-style columnar format.
+///
+/// Each non-blank line contains a single token, with tab separated columns for annotations such
+/// as lemma or part of speech. Sentences are delimited by blank lines. As the format has no
+/// concept of paragraphs, all sentences are placed in a single paragraph.
+///
+/// Comment lines starting with `#`, such as `# sent_id = 1`, are skipped, as are CoNLL-U multiword
+/// token rows, whose ID is a range such as `1-2`, so that the words they span are counted once.
+///
+/// `InputFormat::parse` takes terms from the second column, which holds the word form in CoNLL-U.
+/// Use `ConllFile::read` to take terms from a different column.
+///
+/// # Examples
+///
+/// ```
+/// use fact_graph::input::ConllFile;
+/// use std::io::BufReader;
+///
+/// const INPUT: &str = "\
+/// 1\tcats\tcat\tNOUN
+/// 2\tsleep\tsleep\tVERB
+///
+/// 1\tdogs\tdog\tNOUN
+/// 2\tbark\tbark\tVERB";
+///
+/// let lemmas = ConllFile { column: 2 };
+/// match lemmas.read(BufReader::new(INPUT.as_bytes())) {
+///    Ok(d) => d,
+///    Err(_) => panic!(),
+/// };
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct ConllFile {
+    /// Index of the column containing the term.
+    pub column: usize,
+}
+
+impl Default for ConllFile {
+    fn default() -> Self {
+        Self { column: 1 }
+    }
+}
+
+impl ConllFile {
+    /// Parses a file in CoNLL format into a `Document`, taking terms from the configured column.
+    ///
+    /// Returns an error of kind `InvalidData` if a non-blank line does not have enough columns.
+    pub fn read<F: BufRead>(&self, file: F) -> io::Result<Document> {
+        let mut sentences = Vec::new();
+        let mut sentence = Vec::new();
+        for (n, line) in lines(file, false).enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                if !sentence.is_empty() {
+                    sentences.push(Sentence(mem::take(&mut sentence)));
+                }
+                continue;
+            }
+            if line.starts_with('#') || is_range_id(line.split('\t').next().unwrap()) {
+                continue;
+            }
+            match line.split('\t').nth(self.column) {
+                Some(t) => sentence.push(Term(t.to_string())),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {} has no column {}", n + 1, self.column),
+                    ))
+                }
+            }
+        }
+        if !sentence.is_empty() {
+            sentences.push(Sentence(sentence));
+        }
+        let mut res = Document(Vec::new());
+        if !sentences.is_empty() {
+            res.push(Paragraph(sentences));
+        }
+        Ok(res)
+    }
+}
+
+/// Returns whether `id` is the ID of a CoNLL-U multiword token, such as `1-2`.
+fn is_range_id(id: &str) -> bool {
+    let numeric = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let mut parts = id.splitn(2, '-');
+    numeric(parts.next().unwrap()) && matches!(parts.next(), Some(end) if numeric(end))
+}
+
+impl InputFormat for ConllFile {
+    fn parse<F: BufRead>(file: F) -> io::Result<Document> {
+        ConllFile::default().read(file)
+    }
+}
+
+/// `InputFormat` implementation for raw prose.
+///
+/// Words are separated by whitespace and dashes. A word ending in `.`, `?`, or `!` ends the
+/// current sentence, and a blank line ends the current paragraph, so sentences may span lines.
+/// Terms are lowercased and stripped of non-alphabetic characters; words with no alphabetic
+/// characters are dropped.
+///
+/// This is equivalent to a default `Preprocessor`, which does not remove stopwords.
+///
+/// # Examples
+///
+/// ```
+/// use fact_graph::input::{InputFormat, RawTextFile};
+/// use std::io::BufReader;
+///
+/// const INPUT: &str = "\
+/// The cat sat on the mat. Did the dog
+/// see it? It did!
+///
+/// A new paragraph starts here.";
+///
+/// let d = RawTextFile::parse(BufReader::new(INPUT.as_bytes())).unwrap();
+/// assert_eq!(d.len(), 2);
+/// assert_eq!(d[0].len(), 3);
+/// assert_eq!(d[0][1].len(), 5);
+/// assert_eq!(*d[0][1][4], "it");
+/// ```
+#[allow(missing_debug_implementations, missing_copy_implementations)]
+pub struct RawTextFile;
+
+impl InputFormat for RawTextFile {
+    fn parse<F: BufRead>(file: F) -> io::Result<Document> {
+        Preprocessor::default().parse(file)
+    }
+}
+
+/// `InputFormat` implementation for documents with term counts, such as `cat:2 dog:1`.
+///
+/// Each non-blank line is a sentence of whitespace separated `term:count` entries, and paragraphs
+/// are delimited by blank lines, as in `NddFile`. Each entry is expanded into `count` copies of the
+/// term, so count-based construction methods weigh it accordingly. Entries without a colon, or
+/// with a count that is not a number, have a count of 1.
+///
+/// `InputFormat::parse` returns an error of kind `InvalidData` for a count greater than
+/// `CountedFile::MAX_COUNT`, as expanding it could exhaust memory.
+///
+/// # Examples
+///
+/// ```
+/// use fact_graph::input::{CountedFile, InputFormat};
+///
+/// let d = CountedFile::parse("cat:2 dog".as_bytes()).unwrap();
+/// assert_eq!(d[0][0].len(), 3);
+/// ```
+#[allow(missing_debug_implementations, missing_copy_implementations)]
+pub struct CountedFile;
+
+impl CountedFile {
+    /// Largest count allowed for a single entry.
+    pub const MAX_COUNT: usize = 10_000;
+}
+
+impl InputFormat for CountedFile {
+    fn parse<F: BufRead>(file: F) -> io::Result<Document> {
+        let mut res = Document(Vec::new());
+        let mut in_section = false;
+        for (n, line) in lines(file, false).enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                in_section = false;
+                continue;
+            }
+            if !in_section {
+                in_section = true;
+                res.push(Paragraph(Vec::new()));
+            }
+            let mut sentence = Sentence(Vec::new());
+            for entry in line.split_whitespace() {
+                let (term, count) = match entry.rfind(':') {
+                    Some(i) => {
+                        let count = &entry[i + 1..];
+                        let digits = !count.is_empty() && count.bytes().all(|b| b.is_ascii_digit());
+                        // A count of digits fails to parse only if it overflows.
+                        let default = if digits { usize::MAX } else { 1 };
+                        (&entry[..i], count.parse().unwrap_or(default))
+                    }
+                    None => (entry, 1),
+                };
+                if count > CountedFile::MAX_COUNT {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {} has a count over {}", n + 1, CountedFile::MAX_COUNT),
+                    ));
+                }
+                if !term.is_empty() {
+                    sentence.extend((0..count).map(|_| Term(term.to_string())));
+                }
+            }
+            res.last_mut().unwrap().push(sentence);
+        }
+        Ok(res)
+    }
+}
+
+/// Returns an iterator over the lines of `input`, without line endings.
+///
+/// Lines may end in `\n` or `\r\n`, and a UTF-8 byte order mark at the start of `input` is
+/// removed. If `lossy` is set, invalid UTF-8 is replaced with `U+FFFD REPLACEMENT CHARACTER`;
+/// otherwise it results in an error of kind `InvalidData`, as with `BufRead::lines`.
+pub(crate) fn lines<F: BufRead>(input: F, lossy: bool) -> impl Iterator<Item = io::Result<String>> {
+    bounded_lines(input, lossy, LineLimit::Unlimited)
+}
+
+/// Returns an iterator over the lines of `input` like `lines`, handling long lines according to
+/// `limit`.
+///
+/// At most a line's worth of bytes is buffered. With `LineLimit::Error`, iteration ends after the
+/// error for an over-long line.
+fn bounded_lines<F: BufRead>(
+    mut input: F,
+    lossy: bool,
+    limit: LineLimit,
+) -> impl Iterator<Item = io::Result<String>> {
+    let max = match limit {
+        LineLimit::Unlimited => None,
+        LineLimit::Error(max) | LineLimit::Split(max) => Some(max),
+    };
+    let mut first = true;
+    let mut failed = false;
+    let mut pending = Vec::new();
+    iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        let mut buf = mem::take(&mut pending);
+        if !buf.ends_with(b"\n") {
+            // Reading two bytes past the limit leaves room for a `\r\n` ending.
+            let read = match max {
+                Some(max) => (&mut input)
+                    .take((max.saturating_add(2) - buf.len()) as u64)
+                    .read_until(b'\n', &mut buf),
+                None => input.read_until(b'\n', &mut buf),
+            };
+            if let Err(e) = read {
+                return Some(Err(e));
+            }
+            if buf.is_empty() {
+                return None;
+            }
+        }
+        let ended = buf.ends_with(b"\n");
+        if ended {
+            buf.pop();
+        }
+        if buf.ends_with(b"\r")
+            && (ended || !matches!(max, Some(max) if buf.len() > max.saturating_add(1)))
+        {
+            buf.pop();
+        }
+        if mem::take(&mut first) && buf.starts_with(b"\xef\xbb\xbf") {
+            buf.drain(..3);
+        }
+        match limit {
+            LineLimit::Error(max) if buf.len() > max => {
+                failed = true;
+                return Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line longer than {} bytes", max),
+                )));
+            }
+            LineLimit::Split(max) if buf.len() > max => {
+                let cut = match buf[..=max].iter().rposition(u8::is_ascii_whitespace) {
+                    Some(cut) if cut > 0 => cut,
+                    _ => (1..=max)
+                        .rev()
+                        .find(|&i| buf[i] & 0xc0 != 0x80)
+                        .unwrap_or(max)
+                        .max(1),
+                };
+                pending = buf.split_off(cut);
+                if ended {
+                    pending.push(b'\n');
+                }
+            }
+            _ => (),
+        }
+        Some(if lossy {
+            Ok(String::from_utf8_lossy(&buf).into_owned())
+        } else {
+            String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    })
+}
+
+/// Parses every file in a directory in parallel using the input format `I`.
+///
+/// Returns the name and `Document` of each file, sorted by name. Subdirectories are skipped. If
+/// any file fails to be read or parsed, the error for that file is returned with the file's path
+/// added to its message.
+pub fn parse_dir<I: InputFormat>(dir: &Path) -> io::Result<Vec<(String, Document)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry);
+        }
+    }
+    files.sort_by_key(|e| e.file_name());
+    files
+        .par_iter()
+        .map(|file| {
+            let path = file.path();
+            let with_path =
+                |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", path.display(), e));
+            let document = I::parse(BufReader::new(File::open(&path).map_err(with_path)?))
+                .map_err(with_path)?;
+            Ok((file.file_name().to_string_lossy().into_owned(), document))
+        })
+        .collect()
+}
+
+/// Parses every file in a gzipped tar archive using the input format `I`.
+///
+/// Entries are read from the archive as they are decompressed, so it is not extracted to disk.
+/// Returns the name and `Document` of each file, sorted by name, as with `parse_dir`; files in
+/// directories of the archive are named without the directory, and an error of kind `InvalidData`
+/// is returned if two files have the same name. Entries other than regular files are skipped. If
+/// an entry fails to be read or parsed, the error is returned with the entry's path added to its
+/// message.
+pub fn parse_tar_gz<I: InputFormat>(path: &Path) -> io::Result<Vec<(String, Document)>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(BufReader::new(File::open(path)?)));
+    let mut res = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let entry_path = entry.path()?.into_owned();
+        let with_path =
+            |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", entry_path.display(), e));
+        let name = match entry_path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        let document = I::parse(BufReader::new(entry)).map_err(with_path)?;
+        res.push((name, document));
+    }
+    res.sort_by(|a, b| a.0.cmp(&b.0));
+    if let Some(w) = res.windows(2).find(|w| w[0].0 == w[1].0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("more than one file named {}", w[0].0),
+        ));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a `Document` from nested slices of terms.
+    fn doc(paragraphs: &[&[&[&str]]]) -> Document {
+        Document(
+            paragraphs
+                .iter()
+                .map(|p| {
+                    Paragraph(
+                        p.iter()
+                            .map(|s| Sentence(s.iter().map(|t| Term(t.to_string())).collect()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn document_builder() {
+        let built = DocumentBuilder::new()
+            .paragraph()
+            .sentence(&["cat", "meow"])
+            .sentence(&[])
+            .paragraph()
+            .paragraph()
+            .sentence(&["dog"])
+            .build();
+        let expected = Document(vec![
+            Paragraph(vec![
+                Sentence(vec![Term("cat".to_string()), Term("meow".to_string())]),
+                Sentence(vec![]),
+            ]),
+            Paragraph(vec![Sentence(vec![Term("dog".to_string())])]),
+        ]);
+        assert_eq!(built, expected);
+        assert_eq!(DocumentBuilder::new().build(), Document(Vec::new()));
+    }
+
+    #[test]
+    fn corpus() {
+        let corpus: Corpus = vec![
+            ("a".to_string(), doc(&[&[&["cat", "dog"]], &[&["bird"]]])),
+            ("b".to_string(), doc(&[&[&["dog", "fish"], &["cat"]]])),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(corpus.len(), 2);
+        assert_eq!(
+            corpus.iter().map(|(n, _)| n).collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert_eq!(corpus.iter().nth(1).unwrap().1, &corpus.documents()[1]);
+        assert!(corpus
+            .vocabulary()
+            .into_iter()
+            .eq(vec!["bird", "cat", "dog", "fish"]));
+    }
+
+    #[test]
+    fn term_kinds() {
+        assert_eq!(TermKind::classify("covid19"), TermKind::Mixed);
+        assert_eq!(TermKind::classify("2020"), TermKind::Number);
+        assert_eq!(TermKind::classify("vaccine"), TermKind::Word);
+        assert_eq!(TermKind::classify("3.14"), TermKind::Number);
+        let tagged = TaggedTerm::from(Term("covid19".to_string()));
+        assert_eq!(*tagged.term, "covid19");
+        assert_eq!(tagged.kind, TermKind::Mixed);
+    }
+
+    #[test]
+    fn corpus_metadata() {
+        let mut corpus: Corpus = vec![
+            ("sports-1".to_string(), doc(&[&[&["ball"]]])),
+            ("news-1".to_string(), doc(&[&[&["vote"]]])),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(corpus.metadata_values("class"), [None, None]);
+        corpus
+            .metadata_mut(0)
+            .insert("class".to_string(), "sports".to_string());
+        corpus
+            .metadata_mut(1)
+            .insert("class".to_string(), "news".to_string());
+        corpus
+            .metadata_mut(1)
+            .insert("source".to_string(), "wire".to_string());
+        assert_eq!(
+            corpus.metadata_values("class"),
+            [Some("sports"), Some("news")]
+        );
+        assert_eq!(corpus.metadata_values("source"), [None, Some("wire")]);
+        assert_eq!(corpus.metadata(1)["source"], "wire");
+    }
+
+    #[test]
+    fn corpus_metadata_file() {
+        let mut corpus: Corpus = vec![
+            ("a.txt".to_string(), doc(&[&[&["ball"]]])),
+            ("b.txt".to_string(), doc(&[&[&["vote"]]])),
+            ("c.txt".to_string(), doc(&[&[&["rain"]]])),
+        ]
+        .into_iter()
+        .collect();
+        let input = "a.txt\tsports\nmissing.txt\tnews\n\nc.txt\tweather report\n";
+        corpus.read_metadata("class", input.as_bytes()).unwrap();
+        assert_eq!(
+            corpus.metadata_values("class"),
+            [Some("sports"), None, Some("weather report")]
+        );
+        let mut out = Vec::new();
+        corpus.write_metadata("class", &mut out).unwrap();
+        assert_eq!(out, b"a.txt\tsports\nc.txt\tweather report\n");
+        let err = corpus
+            .read_metadata("class", "b.txt".as_bytes())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let values = read_name_values(&out[..]).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["c.txt"], "weather report");
+    }
+
+    #[test]
+    fn degenerate_documents() {
+        let mut preprocessor = Preprocessor::default();
+        preprocessor.stopwords.insert("the".to_string());
+        preprocessor.stopwords.insert("a".to_string());
+        let corpus: Corpus = vec![
+            (
+                "stopwords",
+                preprocessor.parse("The a.\n\nA the.".as_bytes()).unwrap(),
+            ),
+            ("ok", doc(&[&[&["cat", "dog"]]])),
+            ("single", doc(&[&[&["cat"], &["cat"]]])),
+        ]
+        .into_iter()
+        .map(|(n, d)| (n.to_string(), d))
+        .collect();
+        assert_eq!(corpus.degenerate_documents(), ["stopwords", "single"]);
+    }
+
+    #[test]
+    fn builder() {
+        let mut d = Document(Vec::new());
+        d.push_term(Term("cat".to_string()));
+        d.push_term(Term("meow".to_string()));
+        d.push_sentence();
+        d.push_term(Term("purr".to_string()));
+        d.push_paragraph();
+        d.push_term(Term("dog".to_string()));
+        assert_eq!(d, doc(&[&[&["cat", "meow"], &["purr"]], &[&["dog"]]]));
+    }
+
+    #[test]
+    fn ndd_round_trip() {
+        let raw = "The cat sat on the mat. It purred!\n\nThe dog barked.\nIt ran away.";
+        let preprocessed = Preprocessor::default().parse(raw.as_bytes()).unwrap();
+        let mut written = Vec::new();
+        NddFile::write(&preprocessed, &mut written).unwrap();
+        assert_eq!(
+            String::from_utf8(written.clone()).unwrap(),
+            "the cat sat on the mat\nit purred\n\nthe dog barked\nit ran away"
+        );
+        assert_eq!(NddFile::parse(&written[..]).unwrap(), preprocessed);
+    }
+
+    #[test]
+    fn ndd_sentence_split() {
+        let input = "cats meow. dogs bark! birds?\n\nfish swim";
+        let per_line = NddFile::parse(input.as_bytes()).unwrap();
+        assert_eq!(per_line[0].len(), 1);
+        let format = NddFile {
+            sentence_split: SentenceSplit::OnPunctuation,
+            ..NddFile::default()
+        };
+        assert_eq!(
+            format.read(input.as_bytes()).unwrap(),
+            doc(&[
+                &[&["cats", "meow"], &["dogs", "bark"], &["birds"]],
+                &[&["fish", "swim"]]
+            ])
+        );
+    }
+
+    #[test]
+    fn ndd_sentences() {
+        let input = "cat dog\nbird\n\n\nfish swim. owl\n\n";
+        let flatten =
+            |d: Document| -> Vec<Sentence> { d.0.into_iter().flat_map(|p| p.0).collect() };
+        let format = NddFile::default();
+        let streamed: Vec<Sentence> = format
+            .sentences(input.as_bytes())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, flatten(format.read(input.as_bytes()).unwrap()));
+        let format = NddFile {
+            sentence_split: SentenceSplit::OnPunctuation,
+            paragraph_delimiter: ParagraphDelim::Marker("---".to_string()),
+            ..NddFile::default()
+        };
+        let input = "cat dog\n---\n\nfish swim. owl";
+        let streamed: Vec<Sentence> = format
+            .sentences(input.as_bytes())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, flatten(format.read(input.as_bytes()).unwrap()));
+        assert!(NddFile::default()
+            .sentences(&b"\xff"[..])
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn ndd_paragraph_marker() {
+        let blank = NddFile::parse("cat dog\nbird\n\nfish".as_bytes()).unwrap();
+        let format = NddFile {
+            paragraph_delimiter: ParagraphDelim::Marker("---".to_string()),
+            ..NddFile::default()
+        };
+        let marked = format.read("cat dog\n\nbird\n---\nfish\n ---".as_bytes());
+        assert_eq!(marked.unwrap(), blank);
+    }
+
+    #[test]
+    fn counted() {
+        let d = CountedFile::parse("cat:2 dog:1\nbird: fish:x\n\nowl:0 cat".as_bytes()).unwrap();
+        assert_eq!(
+            d,
+            doc(&[&[&["cat", "cat", "dog"], &["bird", "fish"]], &[&["cat"]]])
+        );
+        let d = CountedFile::parse(format!("cat:{}", CountedFile::MAX_COUNT).as_bytes()).unwrap();
+        assert_eq!(d[0][0].len(), CountedFile::MAX_COUNT);
+        let err = CountedFile::parse("cat:1\ndog:99999999999999999999999".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn ndd_crlf_bom() {
+        let input = "\u{feff}cat dog\r\nbird\r\n\r\nfish\r";
+        let d = NddFile::parse(input.as_bytes()).unwrap();
+        assert_eq!(d, doc(&[&[&["cat", "dog"], &["bird"]], &[&["fish"]]]));
+        let d = ConllFile::parse("\u{feff}1\tcat\r\n2\tdog\r\n".as_bytes()).unwrap();
+        assert_eq!(d, doc(&[&[&["cat", "dog"]]]));
+    }
+
+    #[test]
+    fn ndd_line_limit() {
+        let input = "cat dog bird\nowl fish swim fly\r\n\nantelopes".as_bytes();
+        assert_eq!(
+            NddFile::parse(input).unwrap(),
+            doc(&[
+                &[&["cat", "dog", "bird"], &["owl", "fish", "swim", "fly"]],
+                &[&["antelopes"]]
+            ])
+        );
+        let split = NddFile {
+            line_limit: LineLimit::Split(8),
+            ..NddFile::default()
+        };
+        assert_eq!(
+            split.read(input).unwrap(),
+            doc(&[
+                &[
+                    &["cat", "dog"],
+                    &["bird"],
+                    &["owl", "fish"],
+                    &["swim"],
+                    &["fly"]
+                ],
+                &[&["antelope"], &["s"]]
+            ])
+        );
+        let error = NddFile {
+            line_limit: LineLimit::Error(8),
+            ..NddFile::default()
+        };
+        let err = error.read(input).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.sentences(input).count(), 1);
+        assert!(error.read("cat dog\r\n".as_bytes()).is_ok());
+        for &limit in &[LineLimit::Split(usize::MAX), LineLimit::Error(usize::MAX)] {
+            let format = NddFile {
+                line_limit: limit,
+                ..NddFile::default()
+            };
+            assert_eq!(format.read(input).unwrap(), NddFile::parse(input).unwrap());
+        }
+    }
+
+    #[test]
+    fn ndd_lossy_utf8() {
+        let input = b"caf\xe9 dog\r\nbird";
+        assert_eq!(
+            NddFile::parse(&input[..]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        let format = NddFile {
+            lossy_utf8: true,
+            ..NddFile::default()
+        };
+        let d = format.read(&input[..]).unwrap();
+        assert_eq!(d, doc(&[&[&["caf\u{fffd}", "dog"], &["bird"]]]));
+    }
+
+    #[test]
+    fn conll_sentences() {
+        let input = "1\tThe\tthe\tDET\n\
+                     2\tcats\tcat\tNOUN\n\
+                     \n\
+                     1\tThey\tthey\tPRON\n\
+                     2\tsleep\tsleep\tVERB\n";
+        let d = ConllFile { column: 2 }.read(input.as_bytes()).unwrap();
+        assert_eq!(d, doc(&[&[&["the", "cat"], &["they", "sleep"]]]));
+    }
+
+    #[test]
+    fn conll_parse_form() {
+        let input = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                     2\tcats\tcat\tNOUN\tNNS\tNumber=Plur\t0\troot\t_\tSpaceAfter=No\n\
+                     \n\
+                     1\tNew York\tNew York\tPROPN\tNNP\t_\t0\troot\t_\t_\n";
+        let d = ConllFile::parse(input.as_bytes()).unwrap();
+        assert_eq!(d, doc(&[&[&["The", "cats"], &["New York"]]]));
+    }
+
+    #[test]
+    fn conll_comments_and_multiword_tokens() {
+        let input = "# sent_id = 1\n\
+                     # text = Don't panic.\n\
+                     1-2\tDon't\t_\t_\t_\t_\t_\t_\t_\t_\n\
+                     1\tDo\tdo\tAUX\tVBP\t_\t3\taux\t_\t_\n\
+                     2\tn't\tnot\tPART\tRB\t_\t3\tadvmod\t_\t_\n\
+                     3\tpanic\tpanic\tVERB\tVB\t_\t0\troot\t_\t_\n\
+                     \n\
+                     # sent_id = 2\n\
+                     1\twell-known\twell-known\tADJ\tJJ\t_\t0\troot\t_\t_\n";
+        let d = ConllFile::parse(input.as_bytes()).unwrap();
+        assert_eq!(d, doc(&[&[&["Do", "n't", "panic"], &["well-known"]]]));
+        let words = ConllFile { column: 0 }.read("well-known\n".as_bytes());
+        assert_eq!(words.unwrap(), doc(&[&[&["well-known"]]]));
+    }
+
+    #[test]
+    fn conll_missing_column() {
+        let input = "1\tcats\n2\n";
+        let err = ConllFile { column: 1 }.read(input.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_dir_fixtures() {
+        let dir = std::env::temp_dir().join(format!("fact_graph_parse_dir_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b-second"), "dog bark\n\ndog run").unwrap();
+        fs::write(dir.join("a-first"), "cat meow").unwrap();
+        let parsed = parse_dir::<NddFile>(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let parsed = parsed.unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                ("a-first".to_string(), doc(&[&[&["cat", "meow"]]])),
+                (
+                    "b-second".to_string(),
+                    doc(&[&[&["dog", "bark"]], &[&["dog", "run"]]])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_tar_gz_fixture() {
+        let path =
+            std::env::temp_dir().join(format!("fact_graph_tar_{}.tar.gz", std::process::id()));
+        let parse = |files: &[(&str, &str)]| {
+            let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+                File::create(&path).unwrap(),
+                flate2::Compression::default(),
+            ));
+            for (name, text) in files {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(text.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, name, text.as_bytes())
+                    .unwrap();
+            }
+            builder.into_inner().unwrap().finish().unwrap();
+            let parsed = parse_tar_gz::<NddFile>(&path);
+            fs::remove_file(&path).unwrap();
+            parsed
+        };
+        assert_eq!(
+            parse(&[
+                ("raw_input/b-second", "dog bark\n\ndog run"),
+                ("raw_input/a-first", "cat meow"),
+            ])
+            .unwrap(),
+            vec![
+                ("a-first".to_string(), doc(&[&[&["cat", "meow"]]])),
+                (
+                    "b-second".to_string(),
+                    doc(&[&[&["dog", "bark"]], &[&["dog", "run"]]])
+                ),
+            ]
+        );
+        let err = parse(&[("train/a-first", "cat"), ("test/a-first", "dog")]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
 }
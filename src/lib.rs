@@ -24,4 +24,5 @@ pub mod clustering;
 pub mod construct;
 pub mod graph;
 pub mod input;
+pub mod preprocess;
 pub mod config;
@@ -0,0 +1,920 @@
+//! Preprocessing of raw text.
+//!
+//! Contains the `Preprocessor` type, which segments raw text into sentences and paragraphs and
+//! normalizes the terms in them, the `Pipeline` type for composing term normalization steps, and
+//! the `Tokenizer` trait for splitting text into tokens.
+
+use crate::input::{lines, Document, Term, TermKind};
+use regex::Regex;
+use rust_stemmers::Stemmer;
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, BufRead},
+};
+
+/// How the case of terms is normalized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaseFolding {
+    /// Lowercase all terms.
+    Lowercase,
+    /// Keep terms as written.
+    Preserve,
+    /// Lowercase all terms except those with at least two letters that are all capitals, such as
+    /// acronyms.
+    LowercaseExceptAllCaps,
+}
+
+/// Punctuation commonly attached to the start or end of words.
+pub const PUNCTUATION: &[char] = &[
+    '.', ',', ';', ':', '!', '?', '"', '\'', '(', ')', '[', ']', '{', '}', '<', '>', '-', '‘', '’',
+    '“', '”', '…',
+];
+
+/// How characters other than letters are removed from words.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    /// Split words on hyphens and remove every character that is not a letter (or a digit, if
+    /// digits are kept). If the `Preprocessor` has a `tokenizer`, underscores between letters are
+    /// kept, so that phrases joined by `GazetteerTokenizer` stay intact.
+    Alphabetic,
+    /// Remove the given characters from the start and end of words only, keeping hyphenated
+    /// words, contractions, and decimal numbers intact.
+    TrimPunctuation(Vec<char>),
+}
+
+/// How digits in words are treated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DigitPolicy {
+    /// Remove digits before `normalization` is applied, so numbers are dropped.
+    Drop,
+    /// Keep digits in terms.
+    Keep,
+    /// Replace numbers, words with digits but no letters, with the given term, such as `<num>`.
+    /// The replacement is not case folded, lemmatized, or checked against stopwords. Digits in
+    /// other words are removed as with `Drop`.
+    ReplaceWith(String),
+}
+
+/// Converts raw text into a `Document`.
+///
+/// Words are separated by whitespace and dashes, or by `tokenizer` if one is set. A word ending in
+/// one of `sentence_enders`, by default `.`, `?`, or `!`, ends the current sentence, and a blank
+/// line ends the current paragraph, so sentences may span lines.
+/// Words are normalized into terms according to `normalization` and `case`; terms without any
+/// letters or digits and stopwords are dropped.
+///
+/// # Examples
+///
+/// ```
+/// use fact_graph::preprocess::Preprocessor;
+///
+/// let mut preprocessor = Preprocessor::default();
+/// preprocessor.stopwords.insert("the".to_string());
+/// let d = preprocessor.parse("The cat sat. The dog ran.".as_bytes()).unwrap();
+/// assert_eq!(d[0].len(), 2);
+/// assert_eq!(*d[0][0][0], "cat");
+/// ```
+#[allow(missing_debug_implementations)]
+pub struct Preprocessor {
+    /// Terms to remove, in lowercase. Terms are matched ignoring case, so stopwords are removed
+    /// even if `case` preserves capitals.
+    pub stopwords: HashSet<String>,
+    /// How the case of terms is normalized.
+    pub case: CaseFolding,
+    /// How characters other than letters are removed from words.
+    pub normalization: Normalization,
+    /// How digits in words are treated.
+    pub digit_policy: DigitPolicy,
+    /// Minimum number of terms in a sentence. Shorter sentences, such as headings, are dropped,
+    /// along with paragraphs left without sentences.
+    pub min_sentence_terms: usize,
+    /// Whether invalid UTF-8 is replaced with `U+FFFD REPLACEMENT CHARACTER` rather than returning
+    /// an error. The replacement character is then removed like other punctuation.
+    pub lossy_utf8: bool,
+    /// Lemmatizer applied to terms after case folding. Stopwords are checked against the lemma.
+    pub lemmatizer: Option<Lemmatizer>,
+    /// Whether repeated terms within a sentence are collapsed to their first occurrence, so that
+    /// graph construction counts each pairing at most once per sentence.
+    pub dedup_sentence_terms: bool,
+    /// Minimum number of sentences in a paragraph. Shorter paragraphs, such as lines of dialogue,
+    /// are merged into the previous paragraph, if there is one.
+    pub merge_short_paragraphs: usize,
+    /// Whether a word broken across lines with a hyphen, as is common in scanned text, is joined
+    /// into one term, so that "infor-" and "mation" become "information". This also joins
+    /// hyphenated compounds that happen to be split at a line break.
+    pub dehyphenate: bool,
+    /// Characters that end a sentence when they end a word.
+    pub sentence_enders: HashSet<char>,
+    /// Tokenizer used to split each line into words, instead of splitting on whitespace and
+    /// dashes. Words are normalized as usual, so tokens should keep the characters that end a
+    /// sentence.
+    pub tokenizer: Option<Box<dyn Tokenizer + Send + Sync>>,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Preprocessor {
+            stopwords: HashSet::new(),
+            case: CaseFolding::Lowercase,
+            normalization: Normalization::Alphabetic,
+            digit_policy: DigitPolicy::Drop,
+            min_sentence_terms: 1,
+            lossy_utf8: false,
+            lemmatizer: None,
+            dedup_sentence_terms: false,
+            merge_short_paragraphs: 0,
+            dehyphenate: false,
+            sentence_enders: ['.', '?', '!'].iter().cloned().collect(),
+            tokenizer: None,
+        }
+    }
+}
+
+impl Preprocessor {
+    /// Normalizes a single word.
+    ///
+    /// Returns the resulting term, or `None` if the word should be dropped, along with whether the
+    /// word ends a sentence.
+    pub fn process_word(&self, word: &str) -> (Option<String>, bool) {
+        let end = matches!(word.chars().last(), Some(c) if self.sentence_enders.contains(&c));
+        if let DigitPolicy::ReplaceWith(replacement) = &self.digit_policy {
+            if TermKind::classify(word) == TermKind::Number {
+                return (Some(replacement.clone()), end);
+            }
+        }
+        let word: String = word
+            .chars()
+            .filter(|c| self.digit_policy == DigitPolicy::Keep || !c.is_numeric())
+            .collect();
+        let word: String = match &self.normalization {
+            Normalization::Alphabetic if self.tokenizer.is_some() => word
+                .split('_')
+                .map(|w| {
+                    w.chars()
+                        .filter(|c| c.is_alphanumeric())
+                        .collect::<String>()
+                })
+                .filter(|w| !w.is_empty())
+                .collect::<Vec<_>>()
+                .join("_"),
+            Normalization::Alphabetic => word.chars().filter(|c| c.is_alphanumeric()).collect(),
+            Normalization::TrimPunctuation(chars) => {
+                word.trim_matches(|c| chars.contains(&c)).to_string()
+            }
+        };
+        if !word.chars().any(char::is_alphanumeric) {
+            return (None, end);
+        }
+        let word = match self.case {
+            CaseFolding::Lowercase => word.to_lowercase(),
+            CaseFolding::Preserve => word,
+            CaseFolding::LowercaseExceptAllCaps => {
+                let mut letters = word.chars().filter(|c| c.is_alphabetic());
+                if letters.clone().nth(1).is_some() && letters.all(char::is_uppercase) {
+                    word
+                } else {
+                    word.to_lowercase()
+                }
+            }
+        };
+        let word = match &self.lemmatizer {
+            Some(l) => l.lemmatize(word),
+            None => word,
+        };
+        let stopword = match self.case {
+            CaseFolding::Lowercase => self.stopwords.contains(&word),
+            _ => self.stopwords.contains(&word.to_lowercase()),
+        };
+        if stopword {
+            return (None, end);
+        }
+        (Some(word), end)
+    }
+
+    /// Parses raw text into a `Document`.
+    pub fn parse<F: BufRead>(&self, input: F) -> io::Result<Document> {
+        let mut res = Document(Vec::new());
+        self.parse_into(input, &mut res)?;
+        self.finish(&mut res);
+        Ok(res)
+    }
+
+    /// Parses raw text, appending it to a `Document` that is still being built.
+    ///
+    /// Text continues the last sentence and paragraph of `document`, so a stream of text can be
+    /// parsed in chunks, as long as chunks are split at spaces or after newlines. Call `finish`
+    /// once all the text has been parsed; the result is then the same as parsing all the text at
+    /// once with `parse`, except that words broken at the end of a chunk are not dehyphenated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fact_graph::{input::Document, preprocess::Preprocessor};
+    ///
+    /// let preprocessor = Preprocessor::default();
+    /// let mut d = Document(Vec::new());
+    /// preprocessor.parse_into("The cat".as_bytes(), &mut d).unwrap();
+    /// preprocessor.parse_into(" sat. The dog ran.".as_bytes(), &mut d).unwrap();
+    /// preprocessor.finish(&mut d);
+    /// assert_eq!(d, preprocessor.parse("The cat sat. The dog ran.".as_bytes()).unwrap());
+    /// ```
+    pub fn parse_into<F: BufRead>(&self, input: F, document: &mut Document) -> io::Result<()> {
+        let split_hyphens = self.normalization == Normalization::Alphabetic;
+        // Start of a word broken at the end of the previous line.
+        let mut fragment: Option<String> = None;
+        for line in lines(input, self.lossy_utf8) {
+            let line = line?;
+            let mut words: Vec<String> = match &self.tokenizer {
+                Some(tokenizer) => tokenizer.tokenize(&line),
+                None => line
+                    .split(|c: char| c.is_whitespace() || c == '—' || (split_hyphens && c == '-'))
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            };
+            if words.is_empty() {
+                if !line.trim().is_empty() {
+                    // A tokenizer may find no tokens in a line that is not blank.
+                    continue;
+                }
+                if let Some(f) = fragment.take() {
+                    self.push_word(&f, document);
+                }
+                // Blank line, so end the current paragraph.
+                self.end_sentence(document);
+                self.end_paragraph(document);
+                continue;
+            }
+            if let Some(f) = fragment.take() {
+                words[0].insert_str(0, &f);
+            }
+            if self.dehyphenate && ends_with_broken_word(&line) {
+                let last = words.pop().unwrap();
+                fragment = Some(last.trim_end_matches('-').to_string());
+            }
+            for w in words {
+                self.push_word(&w, document);
+            }
+        }
+        if let Some(f) = fragment {
+            self.push_word(&f, document);
+        }
+        Ok(())
+    }
+
+    /// Normalizes a word and adds it to `document`, ending the sentence if the word does.
+    fn push_word(&self, word: &str, document: &mut Document) {
+        let (term, end) = self.process_word(word);
+        if let Some(term) = term {
+            document.push_term(Term(term));
+        }
+        if end {
+            self.end_sentence(document);
+        }
+    }
+
+    /// Ends the last sentence and paragraph of a `Document` built with `parse_into`.
+    pub fn finish(&self, document: &mut Document) {
+        self.end_sentence(document);
+        self.end_paragraph(document);
+        if matches!(document.last(), Some(p) if p.is_empty()) {
+            document.pop();
+        }
+    }
+
+    /// Ends the last sentence of `document`, dropping it if it has too few terms.
+    ///
+    /// Repeated terms are removed first if `dedup_sentence_terms` is set. The next term starts a
+    /// new sentence.
+    fn end_sentence(&self, document: &mut Document) {
+        let sentence = match document.last_mut().and_then(|p| p.last_mut()) {
+            Some(s) => s,
+            None => return,
+        };
+        if sentence.is_empty() {
+            return;
+        }
+        if self.dedup_sentence_terms {
+            let mut seen = HashSet::new();
+            sentence.retain(|t| seen.insert(t.0.clone()));
+        }
+        if sentence.len() < self.min_sentence_terms {
+            sentence.clear();
+        } else {
+            document.push_sentence();
+        }
+    }
+
+    /// Ends the last paragraph of `document`, if it has any sentences.
+    ///
+    /// The paragraph is merged into the previous one if it is shorter than
+    /// `merge_short_paragraphs`. The next term starts a new paragraph.
+    fn end_paragraph(&self, document: &mut Document) {
+        let paragraph = match document.last_mut() {
+            Some(p) => p,
+            None => return,
+        };
+        // The sentence started by `end_sentence` is empty.
+        if matches!(paragraph.last(), Some(s) if s.is_empty()) {
+            paragraph.pop();
+        }
+        if paragraph.is_empty() {
+            return;
+        }
+        if paragraph.len() < self.merge_short_paragraphs && document.len() > 1 {
+            let short = document.pop().unwrap();
+            document.last_mut().unwrap().extend(short.0);
+        }
+        document.push_paragraph();
+    }
+}
+
+/// Returns whether `line` ends with a word broken by a hyphen, such as "infor-".
+fn ends_with_broken_word(line: &str) -> bool {
+    let line = line.trim_end();
+    line.ends_with('-')
+        && matches!(line[..line.len() - 1].chars().last(), Some(c) if c.is_alphabetic())
+}
+
+/// Replaces terms with their lemma using a dictionary, such as "mice" with "mouse".
+#[derive(Clone, Debug, Default)]
+pub struct Lemmatizer {
+    /// Map from terms to their lemma.
+    pub map: HashMap<String, String>,
+}
+
+impl Lemmatizer {
+    /// Reads a lemma dictionary.
+    ///
+    /// Each non-blank line contains a term followed by its lemma, separated by whitespace. Returns
+    /// an error of kind `InvalidData` if a line does not contain exactly two columns.
+    pub fn read<F: BufRead>(file: F) -> io::Result<Self> {
+        let mut map = HashMap::new();
+        for (n, line) in file.lines().enumerate() {
+            let line = line?;
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            match &columns[..] {
+                [] => (),
+                [term, lemma] => {
+                    map.insert(term.to_string(), lemma.to_string());
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {} does not have two columns", n + 1),
+                    ))
+                }
+            }
+        }
+        Ok(Lemmatizer { map })
+    }
+
+    /// Returns the lemma of a term, or the term itself if it is not in the dictionary.
+    pub fn lemmatize(&self, term: String) -> String {
+        match self.map.get(&term) {
+            Some(lemma) => lemma.clone(),
+            None => term,
+        }
+    }
+}
+
+/// A single step of term normalization, for use in a `Pipeline`.
+pub trait TermTransform {
+    /// Transforms a term, returning `None` if it should be dropped.
+    fn apply(&self, term: String) -> Option<String>;
+}
+
+/// Applies a sequence of `TermTransform`s to terms, in order.
+///
+/// A term dropped by one step is not passed to later steps.
+///
+/// # Examples
+///
+/// ```
+/// use fact_graph::preprocess::{Lowercase, MinLength, Pipeline, TermTransform};
+///
+/// let pipeline = Pipeline {
+///     steps: vec![Box::new(Lowercase), Box::new(MinLength(3))],
+/// };
+/// assert_eq!(pipeline.apply("Cats".to_string()), Some("cats".to_string()));
+/// assert_eq!(pipeline.apply("Is".to_string()), None);
+/// ```
+#[allow(missing_debug_implementations)]
+#[derive(Default)]
+pub struct Pipeline {
+    /// Steps applied to each term.
+    pub steps: Vec<Box<dyn TermTransform>>,
+}
+
+impl TermTransform for Pipeline {
+    fn apply(&self, term: String) -> Option<String> {
+        self.steps.iter().try_fold(term, |t, step| step.apply(t))
+    }
+}
+
+/// Lowercases terms.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lowercase;
+
+impl TermTransform for Lowercase {
+    fn apply(&self, term: String) -> Option<String> {
+        Some(term.to_lowercase())
+    }
+}
+
+/// Drops the given terms.
+#[derive(Clone, Debug, Default)]
+pub struct Stopwords(pub HashSet<String>);
+
+impl TermTransform for Stopwords {
+    fn apply(&self, term: String) -> Option<String> {
+        if self.0.contains(&term) {
+            None
+        } else {
+            Some(term)
+        }
+    }
+}
+
+/// Drops terms with fewer than the given number of characters.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MinLength(pub usize);
+
+impl TermTransform for MinLength {
+    fn apply(&self, term: String) -> Option<String> {
+        if term.chars().count() < self.0 {
+            None
+        } else {
+            Some(term)
+        }
+    }
+}
+
+/// Reduces terms to their stem, such as "running" to "run".
+#[allow(missing_debug_implementations)]
+pub struct Stem(pub Stemmer);
+
+impl TermTransform for Stem {
+    fn apply(&self, term: String) -> Option<String> {
+        Some(self.0.stem(&term).into_owned())
+    }
+}
+
+impl TermTransform for Lemmatizer {
+    fn apply(&self, term: String) -> Option<String> {
+        Some(self.lemmatize(term))
+    }
+}
+
+/// Trait for splitting a line of text into tokens.
+pub trait Tokenizer {
+    /// Returns the tokens in `text`, in order.
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+/// Tokenizer that keeps known multi-word phrases, such as named entities, as single tokens.
+///
+/// Text is split on whitespace, and at each word the longest phrase from the gazetteer that starts
+/// there is matched, ignoring case and any `PUNCTUATION` at the start and end of the phrase. The
+/// words of a matched phrase are joined with underscores; other words are kept as they are.
+///
+/// # Examples
+///
+/// ```
+/// use fact_graph::preprocess::{GazetteerTokenizer, Tokenizer};
+///
+/// let tokenizer = GazetteerTokenizer::new(&["machine learning"]);
+/// assert_eq!(
+///     tokenizer.tokenize("Machine Learning works"),
+///     ["Machine_Learning", "works"]
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct GazetteerTokenizer {
+    phrases: HashSet<String>,
+    max_words: usize,
+}
+
+impl GazetteerTokenizer {
+    /// Creates a tokenizer matching the given phrases, whose words are separated by whitespace.
+    pub fn new<S: AsRef<str>>(phrases: &[S]) -> Self {
+        let phrases: HashSet<String> = phrases
+            .iter()
+            .map(|p| {
+                let words: Vec<_> = p.as_ref().split_whitespace().collect();
+                words.join(" ").to_lowercase()
+            })
+            .filter(|p| !p.is_empty())
+            .collect();
+        let max_words = phrases
+            .iter()
+            .map(|p| p.split(' ').count())
+            .max()
+            .unwrap_or(0);
+        GazetteerTokenizer { phrases, max_words }
+    }
+}
+
+impl Tokenizer for GazetteerTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let mut res = Vec::new();
+        let mut i = 0;
+        while i < words.len() {
+            let longest = (2..=self.max_words.min(words.len() - i)).rev().find(|&n| {
+                let phrase = words[i..i + n].join(" ");
+                self.phrases
+                    .contains(&phrase.trim_matches(PUNCTUATION).to_lowercase())
+            });
+            match longest {
+                Some(n) => {
+                    res.push(words[i..i + n].join("_"));
+                    i += n;
+                }
+                None => {
+                    res.push(words[i].to_string());
+                    i += 1;
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Tokenizer that extracts the non-overlapping matches of a regular expression, such as `\w+`,
+/// or `#\w+` for hashtags. Text between matches is discarded.
+///
+/// When used as the `tokenizer` of a `Preprocessor`, the pattern should include the characters
+/// that end a sentence, such as `#?\w+[.?!]?`, or the text is parsed as a single sentence.
+///
+/// # Examples
+///
+/// ```
+/// use fact_graph::preprocess::{RegexTokenizer, Tokenizer};
+/// use regex::Regex;
+///
+/// let tokenizer = RegexTokenizer {
+///     pattern: Regex::new(r"\w+").unwrap(),
+/// };
+/// assert_eq!(tokenizer.tokenize("don't panic"), ["don", "t", "panic"]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RegexTokenizer {
+    /// Pattern matching a token.
+    pub pattern: Regex,
+}
+
+impl Tokenizer for RegexTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self.pattern
+            .find_iter(text)
+            .map(|m| m.as_str().to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::construct::construct_sentence_count;
+
+    fn terms(preprocessor: &Preprocessor, text: &str) -> Vec<String> {
+        preprocessor
+            .parse(text.as_bytes())
+            .unwrap()
+            .iter()
+            .flat_map(|p| p.iter())
+            .flat_map(|s| s.iter())
+            .map(|t| t.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn case_folding() {
+        let text = "The US told us NASA, a Group.";
+        let mut preprocessor = Preprocessor::default();
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["the", "us", "told", "us", "nasa", "a", "group"]
+        );
+        preprocessor.case = CaseFolding::Preserve;
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["The", "US", "told", "us", "NASA", "a", "Group"]
+        );
+        preprocessor.case = CaseFolding::LowercaseExceptAllCaps;
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["the", "US", "told", "us", "NASA", "a", "group"]
+        );
+    }
+
+    #[test]
+    fn trim_punctuation() {
+        let text = "(covid-19) don't 3.14, \"quoted\"";
+        let mut preprocessor = Preprocessor::default();
+        assert_eq!(terms(&preprocessor, text), ["covid", "dont", "quoted"]);
+        preprocessor.digit_policy = DigitPolicy::Keep;
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["covid", "19", "dont", "314", "quoted"]
+        );
+        preprocessor.normalization = Normalization::TrimPunctuation(PUNCTUATION.to_vec());
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["covid-19", "don't", "3.14", "quoted"]
+        );
+        preprocessor.digit_policy = DigitPolicy::Drop;
+        assert_eq!(terms(&preprocessor, text), ["covid", "don't", "quoted"]);
+    }
+
+    #[test]
+    fn digit_policy() {
+        let mut preprocessor = Preprocessor::default();
+        assert_eq!(preprocessor.process_word("1999."), (None, true));
+        preprocessor.digit_policy = DigitPolicy::Keep;
+        assert_eq!(
+            preprocessor.process_word("1999."),
+            (Some("1999".to_string()), true)
+        );
+        preprocessor.digit_policy = DigitPolicy::ReplaceWith("<num>".to_string());
+        assert_eq!(
+            preprocessor.process_word("1999."),
+            (Some("<num>".to_string()), true)
+        );
+        assert_eq!(
+            preprocessor.process_word("Covid19"),
+            (Some("covid".to_string()), false)
+        );
+        assert_eq!(
+            terms(&preprocessor, "In 1999 and 2000"),
+            ["in", "<num>", "and", "<num>"]
+        );
+    }
+
+    #[test]
+    fn dehyphenate() {
+        let text = "The infor-\nmation age is well-\nknown.\nA dash -\nhere";
+        let mut preprocessor = Preprocessor::default();
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["the", "infor", "mation", "age", "is", "well", "known", "a", "dash", "here"]
+        );
+        preprocessor.dehyphenate = true;
+        assert_eq!(
+            terms(&preprocessor, text),
+            [
+                "the",
+                "information",
+                "age",
+                "is",
+                "wellknown",
+                "a",
+                "dash",
+                "here"
+            ]
+        );
+        preprocessor.normalization = Normalization::TrimPunctuation(PUNCTUATION.to_vec());
+        assert_eq!(terms(&preprocessor, "infor-\nmation.")[0], "information");
+        assert_eq!(
+            terms(&preprocessor, "infor-\n\nmation"),
+            ["infor", "mation"]
+        );
+        assert_eq!(terms(&preprocessor, "infor-"), ["infor"]);
+    }
+
+    #[test]
+    fn sentence_enders() {
+        let text = "The cat sat; the dog ran! Then it slept.";
+        let mut preprocessor = Preprocessor::default();
+        let sentences = |p: &Preprocessor| p.parse(text.as_bytes()).unwrap()[0].len();
+        assert_eq!(sentences(&preprocessor), 2);
+        preprocessor.sentence_enders.insert(';');
+        assert_eq!(sentences(&preprocessor), 3);
+        let d = preprocessor.parse(text.as_bytes()).unwrap();
+        assert_eq!(d[0][0].len(), 3);
+        preprocessor.sentence_enders.remove(&'!');
+        assert_eq!(sentences(&preprocessor), 2);
+    }
+
+    #[test]
+    fn stopwords_ignore_case() {
+        let mut preprocessor = Preprocessor {
+            case: CaseFolding::Preserve,
+            ..Preprocessor::default()
+        };
+        preprocessor.stopwords.insert("the".to_string());
+        preprocessor.stopwords.insert("nasa".to_string());
+        assert_eq!(
+            terms(&preprocessor, "The Cat saw the NASA Dog."),
+            ["Cat", "saw", "Dog"]
+        );
+        preprocessor.case = CaseFolding::LowercaseExceptAllCaps;
+        assert_eq!(terms(&preprocessor, "The CAT"), ["CAT"]);
+    }
+
+    #[test]
+    fn min_sentence_terms() {
+        let text = "Introduction.\n\nThe cat sat. It purred.\n\nEnd.";
+        let preprocessor = Preprocessor {
+            min_sentence_terms: 2,
+            ..Preprocessor::default()
+        };
+        let d = preprocessor.parse(text.as_bytes()).unwrap();
+        assert_eq!(d.len(), 1);
+        assert_eq!(d[0].len(), 2);
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["the", "cat", "sat", "it", "purred"]
+        );
+    }
+
+    #[test]
+    fn merge_short_paragraphs() {
+        let text = "Cat dog.\n\nBird fish.\n\nOwl mouse. Fox hen.";
+        let mut preprocessor = Preprocessor::default();
+        assert_eq!(preprocessor.parse(text.as_bytes()).unwrap().len(), 3);
+        preprocessor.merge_short_paragraphs = 2;
+        let d = preprocessor.parse(text.as_bytes()).unwrap();
+        assert_eq!(d.len(), 2);
+        assert_eq!(d[0].len(), 2);
+        assert_eq!(*d[0][1][0], "bird");
+        assert_eq!(d[1].len(), 2);
+        let d = preprocessor.parse("Cat dog.\n\nOwl mouse. Fox hen.".as_bytes());
+        assert_eq!(d.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn lossy_utf8() {
+        let input = b"The caf\xe9 opened.";
+        let mut preprocessor = Preprocessor::default();
+        assert!(preprocessor.parse(&input[..]).is_err());
+        preprocessor.lossy_utf8 = true;
+        let d = preprocessor.parse(&input[..]).unwrap();
+        assert_eq!(d[0][0].len(), 3);
+        assert_eq!(*d[0][0][1], "caf");
+    }
+
+    #[test]
+    fn chunks() {
+        let text = "Intro.\n\nThe cat sat on the\nmat. It purred.\n\n\nThe dog barked. A bird";
+        let preprocessor = Preprocessor {
+            min_sentence_terms: 2,
+            ..Preprocessor::default()
+        };
+        let expected = preprocessor.parse(text.as_bytes()).unwrap();
+        assert_eq!(expected.len(), 2);
+        let splits =
+            (0..text.len()).filter(|&i| text[i..].starts_with(' ') || text[..i].ends_with('\n'));
+        for split in splits {
+            let mut d = Document(Vec::new());
+            for chunk in &[&text[..split], &text[split..]] {
+                preprocessor.parse_into(chunk.as_bytes(), &mut d).unwrap();
+            }
+            preprocessor.finish(&mut d);
+            assert_eq!(d, expected, "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn dedup_sentence_terms() {
+        let text = "Cat dog dog. Dog bird.";
+        let mut preprocessor = Preprocessor::default();
+        let g = construct_sentence_count(&preprocessor.parse(text.as_bytes()).unwrap());
+        assert_eq!(g.get("cat", "dog"), Ok(&Some(2)));
+        preprocessor.dedup_sentence_terms = true;
+        assert_eq!(terms(&preprocessor, text), ["cat", "dog", "dog", "bird"]);
+        let g = construct_sentence_count(&preprocessor.parse(text.as_bytes()).unwrap());
+        assert_eq!(g.get("cat", "dog"), Ok(&Some(1)));
+        assert_eq!(g.get("dog", "dog"), Ok(&Some(2)));
+    }
+
+    #[test]
+    fn pipeline() {
+        let stopwords = || Box::new(Stopwords(vec!["the".to_string()].into_iter().collect()));
+        let pipeline = Pipeline {
+            steps: vec![Box::new(Lowercase), stopwords()],
+        };
+        assert_eq!(pipeline.apply("The".to_string()), None);
+        assert_eq!(pipeline.apply("Cat".to_string()), Some("cat".to_string()));
+        let reversed = Pipeline {
+            steps: vec![stopwords(), Box::new(Lowercase)],
+        };
+        assert_eq!(reversed.apply("The".to_string()), Some("the".to_string()));
+        let stem = Pipeline {
+            steps: vec![
+                Box::new(Stem(Stemmer::create(rust_stemmers::Algorithm::English))),
+                Box::new(MinLength(3)),
+            ],
+        };
+        assert_eq!(stem.apply("running".to_string()), Some("run".to_string()));
+        assert_eq!(stem.apply("is".to_string()), None);
+    }
+
+    #[test]
+    fn lemmatizer() {
+        let lemmatizer = Lemmatizer::read("mice mouse\n\nbetter good\nran run".as_bytes()).unwrap();
+        let mut preprocessor = Preprocessor {
+            lemmatizer: Some(lemmatizer),
+            ..Preprocessor::default()
+        };
+        assert_eq!(
+            terms(&preprocessor, "Mice ran better than cats."),
+            ["mouse", "run", "good", "than", "cats"]
+        );
+        preprocessor.stopwords.insert("good".to_string());
+        assert_eq!(terms(&preprocessor, "Better mice"), ["mouse"]);
+        let err = Lemmatizer::read("mice mouse\nbetter".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn gazetteer() {
+        let tokenizer = GazetteerTokenizer::new(&["new york", "new york times", "york"]);
+        assert_eq!(tokenizer.tokenize("new york city"), ["new_york", "city"]);
+        assert_eq!(
+            tokenizer.tokenize("the  New York Times in york"),
+            ["the", "New_York_Times", "in", "york"]
+        );
+        assert_eq!(tokenizer.tokenize("new"), ["new"]);
+        assert_eq!(tokenizer.tokenize("(New York)."), ["(New_York)."]);
+        assert!(tokenizer.tokenize("").is_empty());
+    }
+
+    #[test]
+    fn parse_with_gazetteer() {
+        let mut preprocessor = Preprocessor::default();
+        preprocessor.stopwords.insert("in".to_string());
+        let text = "Snow in New York. The New-York Times";
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["snow", "new", "york", "the", "new", "york", "times"]
+        );
+        assert_eq!(terms(&preprocessor, "foo_bar"), ["foobar"]);
+        preprocessor.tokenizer = Some(Box::new(GazetteerTokenizer::new(&[
+            "new york",
+            "new york times",
+        ])));
+        let d = preprocessor.parse(text.as_bytes()).unwrap();
+        assert_eq!(d[0].len(), 2);
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["snow", "new_york", "the", "newyork", "times"]
+        );
+        assert_eq!(terms(&preprocessor, "foo_bar _baz_"), ["foo_bar", "baz"]);
+        preprocessor.normalization = Normalization::TrimPunctuation(PUNCTUATION.to_vec());
+        preprocessor.case = CaseFolding::Preserve;
+        assert_eq!(
+            terms(&preprocessor, text),
+            ["Snow", "New_York", "The", "New-York", "Times"]
+        );
+    }
+
+    #[test]
+    fn regex_tokenizer() {
+        let tokenizer = RegexTokenizer {
+            pattern: Regex::new(r"#\w+").unwrap(),
+        };
+        assert_eq!(
+            tokenizer.tokenize("Loving #rustlang and #ndarray, not # or tags#"),
+            ["#rustlang", "#ndarray"]
+        );
+        assert!(tokenizer.tokenize("no tags here").is_empty());
+    }
+
+    #[test]
+    fn parse_with_regex_tokenizer() {
+        let mut preprocessor = Preprocessor {
+            normalization: Normalization::TrimPunctuation(PUNCTUATION.to_vec()),
+            tokenizer: Some(Box::new(RegexTokenizer {
+                pattern: Regex::new(r"#?\w+[.?!]?").unwrap(),
+            })),
+            ..Preprocessor::default()
+        };
+        let text = "Loving #rustlang today! Tags like #ndarray, too.\n--\nNew-line";
+        let d = preprocessor.parse(text.as_bytes()).unwrap();
+        assert_eq!(d.len(), 1);
+        assert_eq!(d[0].len(), 3);
+        assert_eq!(
+            terms(&preprocessor, text),
+            [
+                "loving",
+                "#rustlang",
+                "today",
+                "tags",
+                "like",
+                "#ndarray",
+                "too",
+                "new",
+                "line"
+            ]
+        );
+        preprocessor.tokenizer = Some(Box::new(RegexTokenizer {
+            pattern: Regex::new(r"#\w+").unwrap(),
+        }));
+        let d = preprocessor.parse(text.as_bytes()).unwrap();
+        assert_eq!(d.len(), 1);
+        assert_eq!(d[0].len(), 1);
+        assert_eq!(terms(&preprocessor, text), ["#rustlang", "#ndarray"]);
+    }
+}